@@ -5,9 +5,34 @@ use std::path::PathBuf;
 fn main() {
     let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
 
-    // Copy hpm-link.x to output directory
+    // Copy hpm-link.x to output directory. With `pma-noncacheable`, mark that
+    // the non-cacheable region is mapped by PMA, so its NAPOT check applies.
     println!("cargo:rerun-if-changed=hpm-link.x");
-    fs::copy("hpm-link.x", out_dir.join("hpm-link.x")).unwrap();
+    let mut link_x = String::new();
+    if env::var_os("CARGO_FEATURE_PMA_NONCACHEABLE").is_some()
+        && env::var_os("CARGO_FEATURE_EMULATOR").is_none()
+    {
+        link_x.push_str("__hpm_pma_noncacheable = 1;\n\n");
+    }
+    // With `pmp`, mark the PMP entries used besides the linker ranges, so
+    // the entry count is checked: the trap stack (U-mode or S-mode) and the
+    // S-mode catch-all.
+    if env::var_os("CARGO_FEATURE_PMP").is_some() {
+        let user = env::var_os("CARGO_FEATURE_USER_MODE").is_some();
+        let supervisor = env::var_os("CARGO_FEATURE_SUPERVISOR_MODE").is_some();
+        let reserved = (user || supervisor) as usize + supervisor as usize;
+        link_x.push_str(&format!("__hpm_pmp_reserved = {reserved};\n\n"));
+    }
+    link_x.push_str(&fs::read_to_string("hpm-link.x").unwrap());
+    fs::write(out_dir.join("hpm-link.x"), link_x).unwrap();
+
+    // Copy optional SDRAM fragment
+    println!("cargo:rerun-if-changed=hpm-sdram.x");
+    fs::copy("hpm-sdram.x", out_dir.join("hpm-sdram.x")).unwrap();
+
+    // Copy memory layout for QEMU virt (`emulator` feature)
+    println!("cargo:rerun-if-changed=memory-qemu-virt.x");
+    fs::copy("memory-qemu-virt.x", out_dir.join("memory-qemu-virt.x")).unwrap();
 
     // Add linker search path
     println!("cargo:rustc-link-search={}", out_dir.display());
@@ -16,4 +41,6 @@ fn main() {
     //   -Tmemory.x    (user-provided memory layout)
     //   -Tdevice.x    (from hpm-metapac, provides __INTERRUPTS)
     //   -Thpm-link.x  (from hpm-riscv-rt)
+    //   -Thpm-sdram.x (from hpm-riscv-rt, optional, boards with SDRAM)
+    //   -Tmemory-qemu-virt.x (from hpm-riscv-rt, instead of memory.x on QEMU)
 }
@@ -3,24 +3,30 @@
  * This is the main linker script for HPMicro RISC-V MCUs.
  * It handles:
  *   - Standard sections (.text, .rodata, .data, .bss)
- *   - Fast sections in ILM/DLM (.fast.text, .fast.data, .fast.bss)
- *   - Vector table placed in ILM (512-byte aligned for PLIC vectored mode)
+ *   - Fast sections in ILM/DLM (.fast.text, .fast.data, .fast.rodata, .fast.bss)
+ *   - Vector table placed in REGION_VECTOR (512-byte aligned for PLIC vectored mode)
  *   - Non-cacheable sections
+ *   - Persistent .noinit section (never zeroed)
+ *   - Copy/zero tables walked by the startup code
+ *   - C/C++ constructor arrays and HPM SDK symbol names
  *
  * Required MEMORY regions (defined in memory.x):
  *   REGION_TEXT, REGION_RODATA, REGION_DATA, REGION_BSS
  *   REGION_HEAP, REGION_STACK
  *   REGION_FASTTEXT (ILM), REGION_FASTDATA (DLM)
+ *   REGION_VECTOR (usually ILM; DLM, AXI SRAM or flash also work)
  *
  * Optional regions:
- *   REGION_NONCACHEABLE_RAM, AHB_SRAM, REGION_CAN
+ *   REGION_NONCACHEABLE_RAM, AHB_SRAM, REGION_CAN, REGION_NOINIT
+ *   REGION_SDRAM, REGION_SDRAM_NONCACHEABLE (with hpm-sdram.x)
  */
 
 ENTRY(_hpm_start);
 
-/* Stack configuration */
+/* Stack configuration: .stack is exactly `_stack_size` bytes below `_stack_start` */
 PROVIDE(_stack_size = 0x4000);
 PROVIDE(_heap_size = 0);
+PROVIDE(_c_heap_size = 0);
 
 /* Multi-hart configuration (single-hart by default) */
 PROVIDE(_max_hart_id = 0);
@@ -30,13 +36,30 @@ PROVIDE(_hart_stack_size = 2K);
 PROVIDE(_stext = ORIGIN(REGION_TEXT));
 PROVIDE(_stack_start = ORIGIN(REGION_STACK) + LENGTH(REGION_STACK));
 
+/* Null-pointer guard: set to a power of two (e.g. `_null_guard_size = 1K;`
+ * in memory.x) to keep the bottom of ILM unused and inaccessible */
+PROVIDE(_null_guard_size = 0);
+
 /* RTT support: provide 0 if defmt-rtt is not linked */
 PROVIDE(_SEGGER_RTT = 0);
 
+/* RTT block in REGION_NONCACHEABLE_RAM: a power of two >= 4K holding the
+ * control block and buffers. Set `_rtt_address` to pin it for probe-rs. */
+PROVIDE(_rtt_size = 4K);
+PROVIDE(_rtt_address = 0);
+
 /* Non-cacheable region: provide 0 if not defined in memory.x */
 PROVIDE(__noncacheable_start__ = 0);
 PROVIDE(__noncacheable_end__ = 0);
 
+/* SDRAM tables and non-cacheable range: empty unless hpm-sdram.x is linked */
+PROVIDE(__sdram_copy_table_start__ = 0);
+PROVIDE(__sdram_copy_table_end__ = 0);
+PROVIDE(__sdram_zero_table_start__ = 0);
+PROVIDE(__sdram_zero_table_end__ = 0);
+PROVIDE(__sdram_noncacheable_start__ = 0);
+PROVIDE(__sdram_noncacheable_end__ = 0);
+
 /* ============ Exception Handlers ============ */
 /* Default to ExceptionHandler if not defined */
 PROVIDE(InstructionMisaligned = ExceptionHandler);
@@ -77,6 +100,18 @@ PROVIDE(hal_main = main);
 /* Pre-initialization function (called before RAM init, interrupts disabled) */
 PROVIDE(__pre_init = default_pre_init);
 
+/* SDRAM init function (called before SDRAM sections are initialized, caches disabled) */
+PROVIDE(__sdram_init = default_sdram_init);
+
+/* Startup phase hooks (see `#[startup_hook]`), called from _hpm_start_rust in this order */
+PROVIDE(__hpm_after_ram_init = default_startup_hook);
+PROVIDE(__hpm_before_cache_enable = default_startup_hook);
+PROVIDE(__hpm_after_cache_enable = default_startup_hook);
+PROVIDE(__hpm_before_interrupts = default_startup_hook);
+
+/* Heap allocation failure hook (`alloc` feature), called before the allocation error */
+PROVIDE(__hpm_oom = default_oom);
+
 /* Interrupt setup function (called after RAM init) */
 PROVIDE(_setup_interrupts = default_setup_interrupts);
 
@@ -103,9 +138,8 @@ SECTIONS
         KEEP(*(.init.rust));
         . = ALIGN(4);
 
-        /* Trap handlers (startup trap, not PLIC vector table) */
+        /* Startup trap handler. CORE_LOCAL (.trap.rust) goes to .fast instead */
         *(.trap);
-        *(.trap.rust);
 
         /* Abort handler */
         *(.text.abort);
@@ -114,46 +148,160 @@ SECTIONS
         *(.text .text.*);
 
         . = ALIGN(4);
+        _etext = .;
     } > REGION_TEXT
 
-    /* Vector table and fast code - placed in ILM */
-    .fast : ALIGN(512)
+    /* Null-pointer guard at the bottom of ILM, locked with PMP entry 0 by
+     * `_hpm_start_rust`. Must come before anything else in REGION_FASTTEXT.
+     */
+    .null_guard (NOLOAD) :
     {
-        _sifast = LOADADDR(.fast);
-        _sfast = .;
+        __null_guard_start__ = .;
+        . += _null_guard_size;
+        __null_guard_end__ = .;
+    } > REGION_FASTTEXT
 
-        /* Vector table must be 512-byte aligned for PLIC vectored mode */
+    /* Vector table - must be 512-byte aligned for PLIC vectored mode.
+     * Copied from flash like .fast, unless REGION_VECTOR is REGION_RODATA itself.
+     */
+    .vector_table : ALIGN(512)
+    {
         __vector_ram_start__ = .;
         /*
-         * CAUTION: ILM starts at 0x00000000.
+         * CAUTION: ILM starts at 0x00000000 (unless `_null_guard_size` is set).
          * Using address 0 as an IRQ handler results in `None` when cast to `Option<fn()>`.
          * The __INTERRUPTS table from hpm-metapac handles this by using CORE_LOCAL at entry 0.
          */
         KEEP(*(.vector_table.interrupts));
+        . = ALIGN(4);
         __vector_ram_end__ = .;
-        . = ALIGN(8);
+    } > REGION_VECTOR AT > REGION_RODATA
+
+    __vector_load_addr__ = LOADADDR(.vector_table);
+
+    /* Fast code - placed in ILM */
+    .fast : ALIGN(4)
+    {
+        _sifast = LOADADDR(.fast);
+        _sfast = .;
 
         /* Fast text section */
         __fast_text_start__ = .;
+        /* Exception and core interrupt entry (CORE_LOCAL) runs from ILM */
         *(.trap.rust);
-        *(.fast .fast.* .fast.text .fast.text.*);
+        /* Not `.fast.*`: that would also swallow .fast.data/.fast.bss/.fast.rodata */
+        *(.fast .fast.text .fast.text.*);
         . = ALIGN(4);
         __fast_text_end__ = .;
 
         _efast = .;
     } > REGION_FASTTEXT AT > REGION_RODATA
 
-    __vector_load_addr__ = LOADADDR(.fast);
     __fast_text_load_addr__ = _sifast;
 
     /* Read-only data */
     .rodata : ALIGN(4)
     {
+        _srodata = .;
         *(.srodata .srodata.*);
         *(.rodata .rodata.*);
+
+        /* U-mode syscall handlers (#[syscall(N)], `user-mode` feature) */
+        . = ALIGN(4);
+        __syscall_table_start__ = .;
+        KEEP(*(.syscall_table .syscall_table.*));
+        __syscall_table_end__ = .;
+
+        /* #[init(stage = ..., priority = N)] functions, sorted by priority */
+        . = ALIGN(4);
+        __hpm_init_pre_ram_start__ = .;
+        KEEP(*(SORT_BY_NAME(.hpm_init.pre_ram.*)));
+        __hpm_init_pre_ram_end__ = .;
+        __hpm_init_post_ram_start__ = .;
+        KEEP(*(SORT_BY_NAME(.hpm_init.post_ram.*)));
+        __hpm_init_post_ram_end__ = .;
+        __hpm_init_pre_main_start__ = .;
+        KEEP(*(SORT_BY_NAME(.hpm_init.pre_main.*)));
+        __hpm_init_pre_main_end__ = .;
+
+        /* C/C++ constructors, run by `_hpm_start_rust` before `main` */
         . = ALIGN(4);
+        __preinit_array_start = .;
+        KEEP(*(.preinit_array));
+        __preinit_array_end = .;
+        __init_array_start = .;
+        KEEP(*(SORT_BY_INIT_PRIORITY(.init_array.*)));
+        KEEP(*(.init_array));
+        __init_array_end = .;
+
+        . = ALIGN(4);
+        _erodata = .;
+    } > REGION_RODATA
+
+    /* Startup copy table: (load address, run address, length in bytes) records.
+     * `_hpm_start` copies every entry before calling `_hpm_start_rust`.
+     * Extra entries come from `.copy_table.*` input sections (`#[init_section]`)
+     * or from user output sections placed with `INSERT AFTER .copy_table`.
+     */
+    .copy_table : ALIGN(4)
+    {
+        __copy_table_start__ = .;
+        LONG(_sidata) LONG(_sdata) LONG(_edata - _sdata)
+        LONG(__vector_load_addr__) LONG(__vector_ram_start__) LONG(__vector_load_addr__ == __vector_ram_start__ ? 0 : __vector_ram_end__ - __vector_ram_start__)
+        LONG(_sifast) LONG(_sfast) LONG(_efast - _sfast)
+        LONG(__fast_data_load_addr__) LONG(__fast_data_start__) LONG(__fast_data_end__ - __fast_data_start__)
+        LONG(__noncacheable_data_load_addr__) LONG(__noncacheable_data_start__) LONG(__noncacheable_data_end__ - __noncacheable_data_start__)
+        LONG(__rtt_load_addr__) LONG(__rtt_start__) LONG(__rtt_data_end__ - __rtt_start__)
+        KEEP(*(.copy_table .copy_table.*));
+    } > REGION_RODATA
+
+    .copy_table_end : ALIGN(4)
+    {
+        __copy_table_end__ = .;
+    } > REGION_RODATA
+
+    /* Startup zero table: (run address, length in bytes) records.
+     * Extended the same way through `.zero_table.*` or `INSERT AFTER .zero_table`.
+     */
+    .zero_table : ALIGN(4)
+    {
+        __zero_table_start__ = .;
+        LONG(_sbss) LONG(_ebss - _sbss)
+        LONG(__fast_bss_start__) LONG(__fast_bss_end__ - __fast_bss_start__)
+        LONG(__noncacheable_bss_start__) LONG(__noncacheable_bss_end__ - __noncacheable_bss_start__)
+        KEEP(*(.zero_table .zero_table.*));
     } > REGION_RODATA
 
+    .zero_table_end : ALIGN(4)
+    {
+        __zero_table_end__ = .;
+    } > REGION_RODATA
+
+    /* RTT control block and buffers (optional), before .data/.bss so they
+     * don't take `_SEGGER_RTT`. One naturally aligned `_rtt_size` block, made
+     * non-cacheable by `hpm67-fix`. The control block is copied at startup,
+     * the buffers are left uninitialized. */
+    .rtt : ALIGN(4)
+    {
+        . = _rtt_address != 0 ? ABSOLUTE(_rtt_address) : ALIGN(_rtt_size);
+        __rtt_start__ = .;
+        KEEP(*(.data._SEGGER_RTT .bss._SEGGER_RTT .rtt .rtt.header));
+        . = ALIGN(4);
+        __rtt_data_end__ = .;
+    } > REGION_NONCACHEABLE_RAM AT > REGION_RODATA
+
+    __rtt_load_addr__ = LOADADDR(.rtt);
+
+    .rtt.buffers (NOLOAD) : ALIGN(4)
+    {
+        KEEP(*(.uninit.defmt-rtt .uninit.defmt-rtt.* .rtt.buffer .rtt.buffer.*));
+        . = ALIGN(4);
+        __rtt_end__ = .;
+        /* Reserve the whole block, so nothing else shares its PMA entry */
+        . = __rtt_end__ > __rtt_start__ ? ABSOLUTE(__rtt_start__ + _rtt_size) : .;
+        __rtt_block_end__ = .;
+    } > REGION_NONCACHEABLE_RAM
+
     /* Initialized data */
     .data : ALIGN(4)
     {
@@ -187,6 +335,10 @@ SECTIONS
     {
         __fast_data_start__ = .;
         *(.fast.data .fast.data.*);
+        /* Lookup tables copied into DLM (#[fast(rodata)]) */
+        *(.fast.rodata .fast.rodata.*);
+        /* HPM SDK ATTR_PLACE_AT_FAST_RAM_INIT */
+        *(.fast_ram.init .fast_ram.init.*);
         . = ALIGN(4);
         __fast_data_end__ = .;
     } > REGION_FASTDATA AT > REGION_RODATA
@@ -198,6 +350,8 @@ SECTIONS
     {
         __fast_bss_start__ = .;
         *(.fast.bss .fast.bss.*);
+        /* HPM SDK ATTR_PLACE_AT_FAST_RAM(_BSS) */
+        *(.fast_ram .fast_ram.bss .fast_ram.bss.*);
         . = ALIGN(4);
         __fast_bss_end__ = .;
     } > REGION_FASTDATA
@@ -207,6 +361,8 @@ SECTIONS
     {
         __noncacheable_data_start__ = .;
         KEEP(*(.noncacheable.data .noncacheable.data.*));
+        /* HPM SDK ATTR_PLACE_AT_NONCACHEABLE_INIT, before .noncacheable.* below */
+        KEEP(*(.noncacheable.init .noncacheable.init.*));
         . = ALIGN(8);
         __noncacheable_data_end__ = .;
     } > REGION_NONCACHEABLE_RAM AT > REGION_RODATA
@@ -222,23 +378,49 @@ SECTIONS
         __noncacheable_bss_end__ = .;
     } > REGION_NONCACHEABLE_RAM
 
-    /* Heap */
+    /* Rest of the non-cacheable range, handed out by `hpm_riscv_rt::dma`.
+     * Reserved, so nothing placed after it in the same region (e.g. .heap or
+     * .stack) overlaps the pool. */
+    .noncacheable.pool (NOLOAD) :
+    {
+        __noncacheable_pool_start__ = .;
+        . = MAX(., ABSOLUTE(__noncacheable_end__));
+        __noncacheable_pool_end__ = .;
+    } > REGION_NONCACHEABLE_RAM
+
+    /* Heap: `_heap_size` bytes for the `alloc` feature, then `_c_heap_size`
+     * bytes for the `c-runtime` `_sbrk` */
     .heap (NOLOAD) :
     {
         _sheap = .;
         . += _heap_size;
         . = ALIGN(4);
         _eheap = .;
+        _sc_heap = .;
+        . += _c_heap_size;
+        . = ALIGN(4);
+        _ec_heap = .;
     } > REGION_HEAP
 
-    /* Stack */
+    /* Stack: `_stack_size` bytes ending at `_stack_start`. Never moved below
+     * the current location, so an overlap with .heap/.bss fails the assertion
+     * below instead of the layout. */
     .stack (NOLOAD) :
     {
+        . = MAX(., ABSOLUTE(_stack_start - _stack_size));
         _estack = .;
         . = ABSOLUTE(_stack_start);
         _sstack = .;
     } > REGION_STACK
 
+    /* Persistent RAM (optional): never initialized by startup code, survives warm reset.
+     * No start/end symbols, so the section can stay empty without REGION_NOINIT.
+     */
+    .noinit (NOLOAD) : ALIGN(4)
+    {
+        KEEP(*(.noinit .noinit.*));
+    } > REGION_NOINIT
+
     /* AHB SRAM (optional) */
     .ahb_sram (NOLOAD) :
     {
@@ -262,6 +444,70 @@ SECTIONS
     .eh_frame_hdr : { *(.eh_frame_hdr) } > REGION_TEXT
 }
 
+/* ============ HPM SDK Compatibility Symbols ============ */
+/* Names used by HPM SDK drivers and syscalls (C code linked through `cc`) */
+PROVIDE(__data_start__ = _sdata);
+PROVIDE(__data_end__ = _edata);
+PROVIDE(__data_load_addr__ = _sidata);
+PROVIDE(__bss_start__ = _sbss);
+PROVIDE(__bss_end__ = _ebss);
+PROVIDE(__ramfunc_start__ = __fast_text_start__);
+PROVIDE(__ramfunc_end__ = __fast_text_end__);
+PROVIDE(__fast_load_addr__ = __fast_text_load_addr__);
+PROVIDE(__fast_ram_init_start__ = __fast_data_start__);
+PROVIDE(__fast_ram_init_end__ = __fast_data_end__);
+PROVIDE(__fast_ram_init_load_addr__ = __fast_data_load_addr__);
+PROVIDE(__fast_ram_bss_start__ = __fast_bss_start__);
+PROVIDE(__fast_ram_bss_end__ = __fast_bss_end__);
+PROVIDE(__noncacheable_init_start__ = __noncacheable_data_start__);
+PROVIDE(__noncacheable_init_end__ = __noncacheable_data_end__);
+PROVIDE(__noncacheable_init_load_addr__ = __noncacheable_data_load_addr__);
+PROVIDE(__heap_start__ = _sheap);
+PROVIDE(__heap_end__ = _eheap);
+PROVIDE(__stack_base__ = _estack);
+PROVIDE(_stack = _sstack);
+PROVIDE(_stack_safe = _sstack);
+PROVIDE(_flash_size = LENGTH(REGION_TEXT));
+
+/* ============ PMP ============ */
+/* Origins of the regions holding read/write sections: `pmp::Builder::from_linker`
+ * merges ranges in the same region, aliases share their origin */
+__data_region_origin__ = ORIGIN(REGION_DATA);
+__bss_region_origin__ = ORIGIN(REGION_BSS);
+__fastdata_region_origin__ = ORIGIN(REGION_FASTDATA);
+__noncacheable_region_origin__ = ORIGIN(REGION_NONCACHEABLE_RAM);
+__heap_region_origin__ = ORIGIN(REGION_HEAP);
+__stack_region_origin__ = ORIGIN(REGION_STACK);
+
+/* Upper bound of the PMP entries `pmp::Builder::from_linker` needs: two per
+ * code range (one for .rodata right after .text) and two per distinct region
+ * with read/write ranges. The non-cacheable region and the heap only count
+ * when not empty, so they come last. */
+__hpm_pmp_nc_used = __noncacheable_pool_end__ > __noncacheable_data_start__ || __rtt_end__ > __rtt_start__;
+__hpm_pmp_rw_regions = 1
+    + (__bss_region_origin__ != __data_region_origin__)
+    + (__fastdata_region_origin__ != __data_region_origin__
+        && __fastdata_region_origin__ != __bss_region_origin__)
+    + (__stack_region_origin__ != __data_region_origin__
+        && __stack_region_origin__ != __bss_region_origin__
+        && __stack_region_origin__ != __fastdata_region_origin__)
+    + (__hpm_pmp_nc_used
+        && __noncacheable_region_origin__ != __data_region_origin__
+        && __noncacheable_region_origin__ != __bss_region_origin__
+        && __noncacheable_region_origin__ != __fastdata_region_origin__
+        && __noncacheable_region_origin__ != __stack_region_origin__)
+    + (_ec_heap > _sheap
+        && __heap_region_origin__ != __data_region_origin__
+        && __heap_region_origin__ != __bss_region_origin__
+        && __heap_region_origin__ != __fastdata_region_origin__
+        && __heap_region_origin__ != __stack_region_origin__
+        && !(__hpm_pmp_nc_used && __heap_region_origin__ == __noncacheable_region_origin__));
+__hpm_pmp_entries = (_null_guard_size != 0)
+    + 2
+    + (_erodata > _srodata ? (_srodata == _etext ? 1 : 2) : 0)
+    + (__fast_text_end__ > __fast_text_start__ ? 2 : 0)
+    + 2 * __hpm_pmp_rw_regions;
+
 /* ============ ASSERTIONS ============ */
 
 ASSERT(ORIGIN(REGION_TEXT) % 4 == 0, "
@@ -297,10 +543,69 @@ BUG(hpm-riscv-rt): start of .heap is not 4-byte aligned");
 ASSERT(_stext + SIZEOF(.text) < ORIGIN(REGION_TEXT) + LENGTH(REGION_TEXT), "
 ERROR(hpm-riscv-rt): .text section exceeds REGION_TEXT");
 
-ASSERT(SIZEOF(.stack) > (_max_hart_id + 1) * _hart_stack_size, "
+ASSERT(_sstack - _estack == _stack_size, "
+ERROR(hpm-riscv-rt): .stack overlaps .heap/.bss or the end of REGION_STACK.
+Reduce `_stack_size`, `_heap_size` or `_c_heap_size`, or move the stack with `_stack_start`.");
+
+ASSERT(_stack_start % 16 == 0, "
+ERROR(hpm-riscv-rt): `_stack_start` must be 16-byte aligned");
+
+ASSERT(_sstack - _estack > (_max_hart_id + 1) * _hart_stack_size, "
 ERROR(hpm-riscv-rt): .stack too small for all harts.
 Consider changing `_max_hart_id` or `_hart_stack_size`.");
 
+ASSERT(__copy_table_start__ % 4 == 0 && (__copy_table_end__ - __copy_table_start__) % 12 == 0, "
+BUG(hpm-riscv-rt): copy table is malformed. Entries are 3 words (src, dst, len).");
+
+ASSERT(__zero_table_start__ % 4 == 0 && (__zero_table_end__ - __zero_table_start__) % 8 == 0, "
+BUG(hpm-riscv-rt): zero table is malformed. Entries are 2 words (dst, len).");
+
+ASSERT(__vector_ram_start__ % 512 == 0, "
+BUG(hpm-riscv-rt): vector table is not 512-byte aligned (required by PLIC vectored mode)");
+
+ASSERT(!DEFINED(__INTERRUPTS) || __INTERRUPTS == __vector_ram_start__, "
+ERROR(hpm-riscv-rt): __INTERRUPTS is not at the start of .vector_table. Is it in a .vector_table.interrupts section?");
+
+/* `__hpm_pma_noncacheable` is defined by build.rs with `pma-noncacheable` */
+ASSERT(!DEFINED(__hpm_pma_noncacheable) || __noncacheable_end__ == __noncacheable_start__ || ((__noncacheable_end__ - __noncacheable_start__) >= 4K
+        && ((__noncacheable_end__ - __noncacheable_start__) & (__noncacheable_end__ - __noncacheable_start__ - 1)) == 0
+        && (__noncacheable_start__ & (__noncacheable_end__ - __noncacheable_start__ - 1)) == 0), "
+ERROR(hpm-riscv-rt): __noncacheable_start__..__noncacheable_end__ must be a power of two >= 4K,
+aligned to its size, to be mapped by a PMA NAPOT entry");
+
+ASSERT(__noncacheable_end__ == 0 || (__noncacheable_data_start__ >= __noncacheable_start__
+        && __noncacheable_bss_end__ <= __noncacheable_end__), "
+ERROR(hpm-riscv-rt): .noncacheable.data/.noncacheable.bss must lie within
+__noncacheable_start__..__noncacheable_end__.");
+
+ASSERT(_rtt_size >= 4K && (_rtt_size & (_rtt_size - 1)) == 0 && (_rtt_address & (_rtt_size - 1)) == 0, "
+ERROR(hpm-riscv-rt): `_rtt_size` must be a power of two >= 4K and `_rtt_address` aligned to it");
+
+ASSERT(__rtt_end__ - __rtt_start__ <= _rtt_size, "
+ERROR(hpm-riscv-rt): RTT control block and buffers do not fit in `_rtt_size`");
+
+ASSERT(_SEGGER_RTT == 0 || (_SEGGER_RTT >= __rtt_start__ && _SEGGER_RTT < __rtt_end__), "
+ERROR(hpm-riscv-rt): _SEGGER_RTT is not in the .rtt section.
+Put it in a `.rtt` input section, or its buffers in `.rtt.buffer`.");
+
+ASSERT(_null_guard_size == 0 || (_null_guard_size >= 8 && (_null_guard_size & (_null_guard_size - 1)) == 0), "
+ERROR(hpm-riscv-rt): _null_guard_size must be 0 or a power of two >= 8");
+
+ASSERT(_null_guard_size == 0 || __null_guard_start__ == 0, "
+ERROR(hpm-riscv-rt): the null-pointer guard needs REGION_FASTTEXT to start at address 0");
+
+ASSERT(!DEFINED(CORE_LOCAL) || (CORE_LOCAL >= __fast_text_start__ && CORE_LOCAL < __fast_text_end__), "
+BUG(hpm-riscv-rt): CORE_LOCAL is not in .fast (ILM). Check that no earlier pattern matches .trap.rust");
+
+ASSERT(!DEFINED(_start_rust_CORE_LOCAL) || (_start_rust_CORE_LOCAL >= __fast_text_start__ && _start_rust_CORE_LOCAL < __fast_text_end__), "
+BUG(hpm-riscv-rt): _start_rust_CORE_LOCAL is not in .fast (ILM). Check that no earlier pattern matches .trap.rust");
+
+/* `__hpm_pmp_reserved` is defined by build.rs with `pmp`: the entries the
+ * startup code needs besides the ranges (trap stack, S-mode catch-all) */
+ASSERT(!DEFINED(__hpm_pmp_reserved) || __hpm_pmp_entries + __hpm_pmp_reserved <= 16, "
+ERROR(hpm-riscv-rt): the `pmp` ranges of this layout may need more than the 16 PMP entries.
+Put .data, .bss, .fast.data/.fast.bss, heap and stack in fewer memory regions.");
+
 ASSERT(SIZEOF(.got) == 0, "
 ERROR(hpm-riscv-rt): .got section detected. Dynamic relocations not supported.
 If linking C code via `cc` crate, compile without -fPIC flag.");
@@ -0,0 +1,123 @@
+/* HPMicro RISC-V SDRAM (FEMC) Link Script Fragment
+ *
+ * Add after hpm-link.x on boards with SDRAM:
+ *   -Thpm-sdram.x
+ *
+ * It handles:
+ *   - Code in SDRAM (.sdram.text)
+ *   - Data in SDRAM (.sdram.data, .sdram.bss)
+ *   - Non-cacheable data in SDRAM (.sdram.noncacheable.data, .sdram.noncacheable.bss)
+ *
+ * Required MEMORY regions (defined in memory.x):
+ *   REGION_SDRAM, REGION_SDRAM_NONCACHEABLE
+ *   (REGION_SDRAM_NONCACHEABLE may alias REGION_SDRAM if unused)
+ *
+ * SDRAM sections are not touched by `_hpm_start`. They are initialized by
+ * `_hpm_start_rust` right after the `__sdram_init` hook has configured
+ * clocks and the FEMC controller, and before the caches are enabled.
+ *
+ * Set `_sdram_zero_bss = 0;` in memory.x to skip zeroing .sdram.bss
+ * (e.g. for huge frame buffers).
+ *
+ * With `pma-noncacheable`, REGION_SDRAM_NONCACHEABLE is made non-cacheable
+ * unless it aliases REGION_SDRAM. It must then be a power of two >= 4K,
+ * aligned to its size.
+ */
+
+PROVIDE(_sdram_zero_bss = 1);
+
+__sdram_noncacheable_start__ = ORIGIN(REGION_SDRAM_NONCACHEABLE);
+__sdram_noncacheable_end__ = ORIGIN(REGION_SDRAM_NONCACHEABLE) == ORIGIN(REGION_SDRAM)
+    ? ORIGIN(REGION_SDRAM_NONCACHEABLE) : ORIGIN(REGION_SDRAM_NONCACHEABLE) + LENGTH(REGION_SDRAM_NONCACHEABLE);
+
+SECTIONS
+{
+    /* Code in SDRAM */
+    .sdram.text : ALIGN(4)
+    {
+        __sdram_text_start__ = .;
+        *(.sdram.text .sdram.text.*);
+        . = ALIGN(4);
+        __sdram_text_end__ = .;
+    } > REGION_SDRAM AT > REGION_RODATA
+
+    __sdram_text_load_addr__ = LOADADDR(.sdram.text);
+
+    /* Initialized data in SDRAM */
+    .sdram.data : ALIGN(4)
+    {
+        __sdram_data_start__ = .;
+        *(.sdram.data .sdram.data.*);
+        . = ALIGN(4);
+        __sdram_data_end__ = .;
+    } > REGION_SDRAM AT > REGION_RODATA
+
+    __sdram_data_load_addr__ = LOADADDR(.sdram.data);
+
+    /* Zeroed data in SDRAM */
+    .sdram.bss (NOLOAD) : ALIGN(4)
+    {
+        __sdram_bss_start__ = .;
+        *(.sdram.bss .sdram.bss.*);
+        . = ALIGN(4);
+        __sdram_bss_end__ = .;
+    } > REGION_SDRAM
+
+    /* Non-cacheable data in SDRAM */
+    .sdram.noncacheable.data : ALIGN(8)
+    {
+        __sdram_noncacheable_data_start__ = .;
+        KEEP(*(.sdram.noncacheable.data .sdram.noncacheable.data.*));
+        . = ALIGN(8);
+        __sdram_noncacheable_data_end__ = .;
+    } > REGION_SDRAM_NONCACHEABLE AT > REGION_RODATA
+
+    __sdram_noncacheable_data_load_addr__ = LOADADDR(.sdram.noncacheable.data);
+
+    .sdram.noncacheable.bss (NOLOAD) : ALIGN(8)
+    {
+        __sdram_noncacheable_bss_start__ = .;
+        KEEP(*(.sdram.noncacheable.bss .sdram.noncacheable.bss.*));
+        . = ALIGN(8);
+        __sdram_noncacheable_bss_end__ = .;
+    } > REGION_SDRAM_NONCACHEABLE
+}
+INSERT AFTER .noncacheable.bss;
+
+/* SDRAM copy/zero tables, same record format as the main tables */
+SECTIONS
+{
+    .sdram.copy_table : ALIGN(4)
+    {
+        __sdram_copy_table_start__ = .;
+        LONG(__sdram_text_load_addr__) LONG(__sdram_text_start__) LONG(__sdram_text_end__ - __sdram_text_start__)
+        LONG(__sdram_data_load_addr__) LONG(__sdram_data_start__) LONG(__sdram_data_end__ - __sdram_data_start__)
+        LONG(__sdram_noncacheable_data_load_addr__) LONG(__sdram_noncacheable_data_start__) LONG(__sdram_noncacheable_data_end__ - __sdram_noncacheable_data_start__)
+        __sdram_copy_table_end__ = .;
+    } > REGION_RODATA
+
+    .sdram.zero_table : ALIGN(4)
+    {
+        __sdram_zero_table_start__ = .;
+        LONG(__sdram_bss_start__) LONG(_sdram_zero_bss ? (__sdram_bss_end__ - __sdram_bss_start__) : 0)
+        LONG(__sdram_noncacheable_bss_start__) LONG(__sdram_noncacheable_bss_end__ - __sdram_noncacheable_bss_start__)
+        __sdram_zero_table_end__ = .;
+    } > REGION_RODATA
+}
+INSERT AFTER .zero_table_end;
+
+/* ============ ASSERTIONS ============ */
+
+ASSERT(ORIGIN(REGION_SDRAM) % 4 == 0, "
+ERROR(hpm-riscv-rt): REGION_SDRAM must be 4-byte aligned");
+
+ASSERT(_sdram_zero_bss == 0 || _sdram_zero_bss == 1, "
+ERROR(hpm-riscv-rt): _sdram_zero_bss must be 0 or 1");
+
+/* `__hpm_pma_noncacheable` is defined by build.rs with `pma-noncacheable` */
+ASSERT(!DEFINED(__hpm_pma_noncacheable) || __sdram_noncacheable_end__ == __sdram_noncacheable_start__
+        || ((__sdram_noncacheable_end__ - __sdram_noncacheable_start__) >= 4K
+        && ((__sdram_noncacheable_end__ - __sdram_noncacheable_start__) & (__sdram_noncacheable_end__ - __sdram_noncacheable_start__ - 1)) == 0
+        && (__sdram_noncacheable_start__ & (__sdram_noncacheable_end__ - __sdram_noncacheable_start__ - 1)) == 0), "
+ERROR(hpm-riscv-rt): REGION_SDRAM_NONCACHEABLE must be a power of two >= 4K, aligned to its size,
+to be mapped by a PMA NAPOT entry. Or alias it to REGION_SDRAM if unused.");
@@ -0,0 +1,6 @@
+fn main() {
+    // The tests/ binaries run on QEMU virt (`emulator` feature). Both scripts
+    // come from hpm-riscv-rt's OUT_DIR, which is on the link search path.
+    println!("cargo:rustc-link-arg-tests=-Tmemory-qemu-virt.x");
+    println!("cargo:rustc-link-arg-tests=-Thpm-link.x");
+}
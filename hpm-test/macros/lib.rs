@@ -0,0 +1,292 @@
+//! Procedural macros for hpm-test
+//!
+//! This crate provides:
+//! - `#[tests]` - Turn a module of `#[test]` functions into a test binary
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, spanned::Spanned, Attribute, Item, ItemFn, ItemMod, ReturnType};
+
+/// How a `#[test]` function is expected to end.
+enum Expect {
+    Pass,
+    Panic,
+    Fault(Option<syn::Ident>),
+}
+
+struct Test {
+    func: ItemFn,
+    expect: Expect,
+}
+
+/// Attribute to turn an inline module into an on-target test binary.
+///
+/// Inside the module:
+/// - `#[test]` marks a test function. It takes no arguments, or
+///   `&mut State` when there is an `#[init]` fixture, and returns `()` or
+///   `Result<(), E>`.
+/// - `#[should_panic]` or `#[should_fault]` / `#[should_fault(LoadFault)]`
+///   on a test inverts its result.
+/// - `#[init]` marks at most one fixture, which runs once before the first
+///   test and may return the `State` passed to the tests.
+///
+/// The generated `#[entry]` runs the tests in order of appearance.
+///
+/// # Example
+///
+/// ```ignore
+/// #[hpm_test::tests]
+/// mod tests {
+///     #[test]
+///     fn it_works() {
+///         assert_eq!(1 + 1, 2);
+///     }
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn tests(args: TokenStream, input: TokenStream) -> TokenStream {
+    if !args.is_empty() {
+        return syn::Error::new(Span::call_site(), "`#[tests]` takes no arguments")
+            .to_compile_error()
+            .into();
+    }
+    let module = parse_macro_input!(input as ItemMod);
+
+    match expand(module) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand(module: ItemMod) -> syn::Result<proc_macro2::TokenStream> {
+    let Some((_, items)) = module.content else {
+        return Err(syn::Error::new(
+            module.span(),
+            "`#[tests]` needs an inline module: `mod tests { ... }`",
+        ));
+    };
+
+    let mut init: Option<ItemFn> = None;
+    let mut tests = Vec::new();
+    let mut others = Vec::new();
+    for item in items {
+        let Item::Fn(mut func) = item else {
+            others.push(item);
+            continue;
+        };
+
+        let is_init = take_attr(&mut func.attrs, "init").is_some();
+        let is_test = take_attr(&mut func.attrs, "test").is_some();
+        let should_panic = take_attr(&mut func.attrs, "should_panic");
+        let should_fault = take_attr(&mut func.attrs, "should_fault");
+
+        if !is_test {
+            if let Some(attr) = should_panic.as_ref().or(should_fault.as_ref()) {
+                return Err(syn::Error::new(
+                    attr.span(),
+                    "only `#[test]` functions can be expected to panic or fault",
+                ));
+            }
+        }
+        if is_init && is_test {
+            return Err(syn::Error::new(
+                func.sig.ident.span(),
+                "a function cannot be both `#[init]` and `#[test]`",
+            ));
+        }
+
+        if is_init {
+            if let Some(first) = &init {
+                return Err(syn::Error::new(
+                    func.sig.ident.span(),
+                    format!("`#[init]` already defined as `{}`", first.sig.ident),
+                ));
+            }
+            check_signature(&func)?;
+            if !func.sig.inputs.is_empty() {
+                return Err(syn::Error::new(
+                    func.sig.inputs.span(),
+                    "`#[init]` takes no arguments",
+                ));
+            }
+            init = Some(func);
+        } else if is_test {
+            check_signature(&func)?;
+            let expect = match (should_panic, should_fault) {
+                (Some(attr), Some(_)) => {
+                    return Err(syn::Error::new(
+                        attr.span(),
+                        "`#[should_panic]` and `#[should_fault]` are exclusive",
+                    ))
+                }
+                (Some(attr), None) => {
+                    attr.meta.require_path_only()?;
+                    Expect::Panic
+                }
+                (None, Some(attr)) => match &attr.meta {
+                    syn::Meta::Path(_) => Expect::Fault(None),
+                    syn::Meta::List(list) => Expect::Fault(Some(list.parse_args()?)),
+                    syn::Meta::NameValue(_) => {
+                        return Err(syn::Error::new(
+                            attr.span(),
+                            "expected `#[should_fault]` or `#[should_fault(Exception)]`",
+                        ))
+                    }
+                },
+                (None, None) => Expect::Pass,
+            };
+            tests.push(Test { func, expect });
+        } else {
+            others.push(Item::Fn(func));
+        }
+    }
+
+    let state_ty = match init.as_ref().map(|f| &f.sig.output) {
+        Some(ReturnType::Type(_, ty)) => Some(ty.clone()),
+        _ => None,
+    };
+
+    let mut wrappers = Vec::new();
+    let mut entries = Vec::new();
+    for test in &tests {
+        let sig = &test.func.sig;
+        let name = &sig.ident;
+        let wrapper = format_ident!("__hpm_test_{}", name);
+
+        let args = match (sig.inputs.len(), &state_ty) {
+            (0, _) => quote!(),
+            (1, Some(_)) => quote!(
+                // SAFETY: written by the `#[init]` wrapper before the first test
+                unsafe { (*core::ptr::addr_of_mut!(__HPM_TEST_STATE)).assume_init_mut() }
+            ),
+            (1, None) => {
+                return Err(syn::Error::new(
+                    sig.inputs.span(),
+                    "test takes a state argument, but there is no `#[init]` returning one",
+                ))
+            }
+            _ => {
+                return Err(syn::Error::new(
+                    sig.inputs.span(),
+                    "tests take no arguments or `&mut State`",
+                ))
+            }
+        };
+        let call = if sig.unsafety.is_some() {
+            quote!(unsafe { #name(#args) })
+        } else {
+            quote!(#name(#args))
+        };
+        wrappers.push(quote!(
+            fn #wrapper() -> bool {
+                ::hpm_test::__export::check(#call)
+            }
+        ));
+
+        let expect = match &test.expect {
+            Expect::Pass => quote!(::hpm_test::__export::Expect::Pass),
+            Expect::Panic => quote!(::hpm_test::__export::Expect::Panic),
+            Expect::Fault(None) => quote!(::hpm_test::__export::Expect::Fault(None)),
+            Expect::Fault(Some(exception)) => quote!(
+                ::hpm_test::__export::Expect::Fault(Some(::hpm_test::Exception::#exception))
+            ),
+        };
+        let name_str = name.to_string();
+        entries.push(quote!(
+            ::hpm_test::__export::Test {
+                name: #name_str,
+                run: #wrapper,
+                expect: #expect,
+            }
+        ));
+    }
+
+    let init_wrapper = match &init {
+        Some(func) => {
+            let name = &func.sig.ident;
+            let call = if func.sig.unsafety.is_some() {
+                quote!(unsafe { #name() })
+            } else {
+                quote!(#name())
+            };
+            match &state_ty {
+                Some(ty) => quote!(
+                    static mut __HPM_TEST_STATE: core::mem::MaybeUninit<#ty> =
+                        core::mem::MaybeUninit::uninit();
+
+                    fn __hpm_test_init() {
+                        let state = #call;
+                        // SAFETY: runs once, before any test
+                        unsafe {
+                            core::ptr::addr_of_mut!(__HPM_TEST_STATE)
+                                .write(core::mem::MaybeUninit::new(state))
+                        };
+                    }
+                ),
+                None => quote!(
+                    fn __hpm_test_init() {
+                        #call
+                    }
+                ),
+            }
+        }
+        None => quote!(),
+    };
+    let init_arg = if init.is_some() {
+        quote!(Some(__hpm_test_init))
+    } else {
+        quote!(None)
+    };
+
+    let attrs = &module.attrs;
+    let vis = &module.vis;
+    let ident = &module.ident;
+    let test_fns = tests.iter().map(|t| &t.func);
+    let count = tests.len();
+
+    Ok(quote!(
+        #(#attrs)*
+        #vis mod #ident {
+            #(#others)*
+
+            #init
+
+            #(#test_fns)*
+
+            #init_wrapper
+
+            #(#wrappers)*
+
+            #[::hpm_test::__export::entry]
+            fn __hpm_test_main() -> ! {
+                static TESTS: [::hpm_test::__export::Test; #count] = [#(#entries),*];
+
+                ::hpm_test::__export::run(#init_arg, &TESTS)
+            }
+        }
+    ))
+}
+
+/// Remove the attribute `#[name]` (or `#[name(...)]`) and return it.
+fn take_attr(attrs: &mut Vec<Attribute>, name: &str) -> Option<Attribute> {
+    let index = attrs.iter().position(|a| a.path().is_ident(name))?;
+    Some(attrs.remove(index))
+}
+
+fn check_signature(func: &ItemFn) -> syn::Result<()> {
+    let sig = &func.sig;
+    if sig.asyncness.is_some() || sig.constness.is_some() || sig.abi.is_some() {
+        return Err(syn::Error::new(
+            sig.span(),
+            "test functions must be plain `fn`s",
+        ));
+    }
+    if !sig.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            sig.generics.span(),
+            "test functions cannot be generic",
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,542 @@
+//! On-target test harness for hpm-riscv-rt.
+//!
+//! `#[hpm_test::tests]` turns a module into a test binary: it generates the
+//! `#[entry]`, which runs every `#[test]` function in turn and reports the
+//! results over semihosting (default) or RTT (`rtt` feature).
+//!
+//! ```ignore
+//! // tests/startup.rs, with `harness = false` for this [[test]] in Cargo.toml
+//! #![no_std]
+//! #![no_main]
+//!
+//! #[hpm_test::tests]
+//! mod tests {
+//!     struct State {
+//!         ticks: u32,
+//!     }
+//!
+//!     /// Runs once, before the first test
+//!     #[init]
+//!     fn init() -> State {
+//!         State { ticks: 0 }
+//!     }
+//!
+//!     #[test]
+//!     fn counts(state: &mut State) {
+//!         state.ticks += 1;
+//!         assert_eq!(state.ticks, 1);
+//!     }
+//!
+//!     #[test]
+//!     fn returns_result() -> Result<(), &'static str> {
+//!         Ok(())
+//!     }
+//!
+//!     #[test]
+//!     #[should_panic]
+//!     fn panics() {
+//!         panic!("expected");
+//!     }
+//!
+//!     #[test]
+//!     #[should_fault(LoadFault)]
+//!     fn reads_unmapped() {
+//!         unsafe { (0x3000_0000 as *const u32).read_volatile() };
+//!     }
+//! }
+//! ```
+//!
+//! A test fails if it panics, takes an exception or returns `Err`, unless it
+//! is marked `#[should_panic]` or `#[should_fault]` (optionally naming the
+//! exception, as in the runtime's exception handler names). Either way the
+//! run goes on with the next test: the failed test's stack is discarded
+//! without running destructors, and the `#[init]` state keeps whatever the
+//! test left in it.
+//!
+//! This also holds for a panic or fault inside an interrupt or exception
+//! handler: the harness leaves the trap with `mret`, back in the runner's
+//! mode with the `mstatus.MIE` it had before the test, and completes every
+//! enabled PLIC source, since the claimed one is not known. Panics leave
+//! through an `ecall`, so a `MachineEnvCall` handler must fall through to
+//! `ExceptionHandler`.
+//!
+//! The harness provides the `#[panic_handler]` and `ExceptionHandler`, so
+//! the test binary must not link another panic handler. Exceptions with
+//! their own handler (e.g. a `LoadFault` under test) still reach
+//! `ExceptionHandler` after it. Tests run in the mode `main` runs in; the
+//! `supervisor-mode` feature of hpm-riscv-rt is not supported.
+//!
+//! With `semihosting`, the binary exits with status 0 if all tests passed,
+//! so it can be used as a cargo runner target under
+//! `qemu-system-riscv32 -semihosting` (with the `emulator` feature of
+//! hpm-riscv-rt) or a debugger. With `rtt`, it spins after the summary.
+
+#![no_std]
+
+#[cfg(not(any(feature = "semihosting", feature = "rtt")))]
+compile_error!("hpm-test needs the `semihosting` or `rtt` feature to report results");
+
+#[cfg(feature = "rtt")]
+mod rtt;
+
+use core::arch::global_asm;
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+use andes_riscv::plic::Plic;
+use hpm_riscv_rt::TrapFrame;
+use riscv::register::{mcause, mepc, mscratch, mstatus, mtval};
+
+pub use hpm_test_macros::tests;
+
+/// An exception, named as its handler in hpm-riscv-rt.
+#[allow(missing_docs)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(usize)]
+pub enum Exception {
+    InstructionMisaligned = 0,
+    InstructionFault = 1,
+    IllegalInstruction = 2,
+    Breakpoint = 3,
+    LoadMisaligned = 4,
+    LoadFault = 5,
+    StoreMisaligned = 6,
+    StoreFault = 7,
+    UserEnvCall = 8,
+    SupervisorEnvCall = 9,
+    MachineEnvCall = 11,
+    InstructionPageFault = 12,
+    LoadPageFault = 13,
+    StorePageFault = 15,
+}
+
+impl Exception {
+    /// The exception with `mcause` code `code`.
+    pub fn from_code(code: usize) -> Option<Exception> {
+        use Exception::*;
+
+        Some(match code {
+            0 => InstructionMisaligned,
+            1 => InstructionFault,
+            2 => IllegalInstruction,
+            3 => Breakpoint,
+            4 => LoadMisaligned,
+            5 => LoadFault,
+            6 => StoreMisaligned,
+            7 => StoreFault,
+            8 => UserEnvCall,
+            9 => SupervisorEnvCall,
+            11 => MachineEnvCall,
+            12 => InstructionPageFault,
+            13 => LoadPageFault,
+            15 => StorePageFault,
+            _ => return None,
+        })
+    }
+}
+
+/// Return type of a `#[test]` function: `()` or `Result<(), E>`.
+pub trait TestOutcome {
+    /// The error the test returned, if it failed.
+    fn failure(&self) -> Option<&dyn fmt::Debug>;
+}
+
+impl TestOutcome for () {
+    fn failure(&self) -> Option<&dyn fmt::Debug> {
+        None
+    }
+}
+
+impl<E: fmt::Debug> TestOutcome for Result<(), E> {
+    fn failure(&self) -> Option<&dyn fmt::Debug> {
+        self.as_ref().err().map(|e| e as &dyn fmt::Debug)
+    }
+}
+
+/// Used by the code `#[tests]` generates.
+#[doc(hidden)]
+pub mod __export {
+    pub use hpm_riscv_rt::entry;
+
+    pub use crate::{check, run, Expect, Test};
+}
+
+/// How a test is expected to end.
+#[doc(hidden)]
+#[derive(Clone, Copy)]
+pub enum Expect {
+    Pass,
+    Panic,
+    Fault(Option<crate::Exception>),
+}
+
+/// A `#[test]` function.
+#[doc(hidden)]
+pub struct Test {
+    pub name: &'static str,
+    /// Runs the test, returns false if it failed (already reported)
+    pub run: fn() -> bool,
+    pub expect: Expect,
+}
+
+/// `CURRENT` outside of tests.
+const IDLE: usize = usize::MAX;
+/// `CURRENT` while the `#[init]` fixture runs.
+const INIT: usize = usize::MAX - 1;
+
+static TESTS: AtomicPtr<Test> = AtomicPtr::new(core::ptr::null_mut());
+static TEST_COUNT: AtomicUsize = AtomicUsize::new(0);
+/// Index of the next test to run.
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+/// Index of the running test, `INIT` or `IDLE`.
+static CURRENT: AtomicUsize = AtomicUsize::new(IDLE);
+static PASSED: AtomicUsize = AtomicUsize::new(0);
+static FAILED: AtomicUsize = AtomicUsize::new(0);
+
+/// Stack pointer of `run`, where the runner resumes after a panic or fault.
+static RUNNER_SP: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether the runner (and so the tests) runs in M-mode rather than U-mode.
+static RUNNER_IN_M: AtomicBool = AtomicBool::new(true);
+/// `mstatus.MIE` of the runner before the current test, M-mode only.
+static RUNNER_MIE: AtomicBool = AtomicBool::new(false);
+
+/// No `ecall` from the harness in flight.
+const ECALL_NONE: usize = 0;
+/// `run` finds out the runner's mode.
+const ECALL_PROBE: usize = 1;
+/// The panic handler leaves the panicking code.
+const ECALL_RESUME: usize = 2;
+/// Set by `ExceptionHandler` once it handled the `ecall`.
+const ECALL_TAKEN: usize = 3;
+
+/// Purpose of the harness's `ecall` in flight.
+static ECALL: AtomicUsize = AtomicUsize::new(ECALL_NONE);
+
+/// Set by `ExceptionHandler` until the runner has resumed.
+static FAULTED: AtomicBool = AtomicBool::new(false);
+static FAULT_CAUSE: AtomicUsize = AtomicUsize::new(0);
+static FAULT_PC: AtomicUsize = AtomicUsize::new(0);
+static FAULT_TVAL: AtomicUsize = AtomicUsize::new(0);
+
+// Abandon the stack of the failed test and continue with the next one.
+// Entered by a jump from the panic handler or by `mret` after an exception.
+global_asm!(
+    r#"
+    .section .text.__hpm_test_resume, "ax"
+    .global __hpm_test_resume
+    .type __hpm_test_resume, @function
+    .balign 4
+
+__hpm_test_resume:
+    la t0, {sp}
+    lw sp, 0(t0)
+    j {resumed}
+
+    .size __hpm_test_resume, . - __hpm_test_resume
+"#,
+    sp = sym RUNNER_SP,
+    resumed = sym resumed,
+);
+
+extern "C" {
+    fn __hpm_test_resume() -> !;
+}
+
+/// Output to the host.
+struct Output;
+
+impl Write for Output {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        #[cfg(feature = "semihosting")]
+        hpm_riscv_rt::semihosting::write(s.as_bytes());
+        #[cfg(feature = "rtt")]
+        rtt::write(s.as_bytes());
+        Ok(())
+    }
+}
+
+fn tests() -> &'static [Test] {
+    let tests = TESTS.load(Ordering::Relaxed);
+    // SAFETY: set from a `&'static [Test]` by `run`.
+    unsafe { core::slice::from_raw_parts(tests, TEST_COUNT.load(Ordering::Relaxed)) }
+}
+
+/// Run the `#[init]` fixture, then all tests. Called from the generated
+/// `main`.
+#[doc(hidden)]
+pub fn run(init: Option<fn()>, tests: &'static [Test]) -> ! {
+    #[cfg(feature = "rtt")]
+    rtt::init();
+
+    TESTS.store(tests.as_ptr() as *mut Test, Ordering::Relaxed);
+    TEST_COUNT.store(tests.len(), Ordering::Relaxed);
+    RUNNER_IN_M.store(ecall(ECALL_PROBE), Ordering::Relaxed);
+    let sp: usize;
+    // SAFETY: only reads sp. Frames below it belong to the tests.
+    unsafe { core::arch::asm!("mv {}, sp", out(reg) sp) };
+    RUNNER_SP.store(sp, Ordering::Relaxed);
+
+    let plural = if tests.len() == 1 { "" } else { "s" };
+    let _ = writeln!(Output, "\nrunning {} test{}", tests.len(), plural);
+
+    if let Some(init) = init {
+        save_interrupt_enable();
+        CURRENT.store(INIT, Ordering::SeqCst);
+        init();
+        CURRENT.store(IDLE, Ordering::SeqCst);
+    }
+    run_tests()
+}
+
+fn run_tests() -> ! {
+    let tests = tests();
+    loop {
+        let index = NEXT.load(Ordering::Relaxed);
+        let Some(test) = tests.get(index) else {
+            finish();
+        };
+        NEXT.store(index + 1, Ordering::Relaxed);
+
+        let _ = write!(Output, "test {} ... ", test.name);
+        save_interrupt_enable();
+        CURRENT.store(index, Ordering::SeqCst);
+        let passed = (test.run)();
+        CURRENT.store(IDLE, Ordering::SeqCst);
+
+        if passed {
+            match test.expect {
+                Expect::Pass => pass(),
+                Expect::Panic => fail(format_args!("did not panic")),
+                Expect::Fault(_) => fail(format_args!("did not fault")),
+            }
+        }
+    }
+}
+
+/// Issue an `ecall` for `purpose` and return whether `ExceptionHandler`
+/// took it, i.e. whether it was made from M-mode. From U-mode it reaches the
+/// runtime's syscall dispatcher, which returns for the unknown number.
+fn ecall(purpose: usize) -> bool {
+    ECALL.store(purpose, Ordering::SeqCst);
+    // SAFETY: no syscall has number usize::MAX, `a0` is clobbered by its result.
+    unsafe {
+        core::arch::asm!("ecall", in("a7") usize::MAX, lateout("a0") _, options(nostack));
+    }
+    ECALL.swap(ECALL_NONE, Ordering::SeqCst) == ECALL_TAKEN
+}
+
+/// Remember the runner's `mstatus.MIE`, restored when a test is abandoned.
+fn save_interrupt_enable() {
+    if RUNNER_IN_M.load(Ordering::Relaxed) {
+        RUNNER_MIE.store(mstatus::read().mie(), Ordering::Relaxed);
+    }
+}
+
+/// Report the outcome of a test function. Called from the generated test
+/// wrappers.
+#[doc(hidden)]
+pub fn check<T: TestOutcome>(outcome: T) -> bool {
+    match outcome.failure() {
+        Some(error) => {
+            fail(format_args!("returned Err({:?})", error));
+            false
+        }
+        None => true,
+    }
+}
+
+fn pass() {
+    PASSED.fetch_add(1, Ordering::Relaxed);
+    let _ = writeln!(Output, "ok");
+}
+
+fn fail(reason: fmt::Arguments) {
+    FAILED.fetch_add(1, Ordering::Relaxed);
+    let _ = writeln!(Output, "FAILED: {}", reason);
+}
+
+fn finish() -> ! {
+    let passed = PASSED.load(Ordering::Relaxed);
+    let failed = FAILED.load(Ordering::Relaxed);
+    let result = if failed == 0 { "ok" } else { "FAILED" };
+    let _ = writeln!(
+        Output,
+        "\ntest result: {}. {} passed; {} failed",
+        result, passed, failed
+    );
+    exit(if failed == 0 { 0 } else { 1 })
+}
+
+/// End the run early, e.g. when the `#[init]` fixture fails.
+fn abort(reason: fmt::Arguments) -> ! {
+    let _ = writeln!(Output, "\nerror: {}", reason);
+    exit(1)
+}
+
+fn exit(status: i32) -> ! {
+    #[cfg(feature = "semihosting")]
+    hpm_riscv_rt::semihosting::exit(status);
+
+    #[cfg(not(feature = "semihosting"))]
+    {
+        let _ = status;
+        loop {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// Continue after a failed test, on the runner's stack.
+extern "C" fn resumed() -> ! {
+    ECALL.store(ECALL_NONE, Ordering::SeqCst);
+    if FAULTED.load(Ordering::SeqCst) {
+        let code = FAULT_CAUSE.load(Ordering::Relaxed);
+        let pc = FAULT_PC.load(Ordering::Relaxed);
+        let tval = FAULT_TVAL.load(Ordering::Relaxed);
+        FAULTED.store(false, Ordering::SeqCst);
+
+        let exception = Exception::from_code(code);
+        let fault = Fault {
+            exception,
+            code,
+            pc,
+            tval,
+        };
+        match CURRENT.swap(IDLE, Ordering::SeqCst) {
+            INIT => abort(format_args!("init {}", fault)),
+            IDLE => abort(format_args!("{} outside a test", fault)),
+            index => match tests()[index].expect {
+                Expect::Fault(None) => pass(),
+                Expect::Fault(Some(expected)) if exception == Some(expected) => pass(),
+                Expect::Fault(Some(expected)) => {
+                    fail(format_args!("{}, expected {:?}", fault, expected))
+                }
+                _ => fail(format_args!("{}", fault)),
+            },
+        }
+    }
+    run_tests()
+}
+
+struct Fault {
+    exception: Option<Exception>,
+    code: usize,
+    pc: usize,
+    tval: usize,
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.exception {
+            Some(exception) => write!(f, "{:?}", exception)?,
+            None => write!(f, "exception {}", self.code)?,
+        }
+        write!(f, " at {:#010x} (mtval {:#010x})", self.pc, self.tval)
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    let message = PanicMessage(info);
+    match CURRENT.swap(IDLE, Ordering::SeqCst) {
+        INIT => abort(format_args!("init {}", message)),
+        IDLE => abort(format_args!("{} outside a test", message)),
+        index => match tests()[index].expect {
+            Expect::Panic => pass(),
+            _ => fail(format_args!("{}", message)),
+        },
+    }
+    // Leave through `ExceptionHandler`, which also returns from a trap
+    // handler that panicked. Returns only in U-mode, where no handler runs.
+    ecall(ECALL_RESUME);
+    // SAFETY: the runner's stack is intact above RUNNER_SP.
+    unsafe { __hpm_test_resume() }
+}
+
+struct PanicMessage<'a>(&'a PanicInfo<'a>);
+
+impl fmt::Display for PanicMessage<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "panicked")?;
+        if let Some(location) = self.0.location() {
+            write!(f, " at {}", location)?;
+        }
+        write!(f, ": {}", self.0.message())
+    }
+}
+
+/// Record the exception and return from the trap into the runner.
+///
+/// Also called after exception-specific handlers, so only the first call
+/// per exception counts.
+#[no_mangle]
+extern "C" fn ExceptionHandler(_trap_frame: &TrapFrame) {
+    match ECALL.load(Ordering::SeqCst) {
+        ECALL_PROBE if mcause::read().code() == 11 => {
+            ECALL.store(ECALL_TAKEN, Ordering::SeqCst);
+            // SAFETY: steps over the 4-byte `ecall`.
+            unsafe { mepc::write(mepc::read() + 4) };
+            return;
+        }
+        ECALL_RESUME if mcause::read().code() == 11 => {
+            ECALL.store(ECALL_TAKEN, Ordering::SeqCst);
+            // SAFETY: the panicking code is abandoned.
+            unsafe { resume_runner() };
+            return;
+        }
+        ECALL_TAKEN => return,
+        _ => {}
+    }
+    if FAULTED.load(Ordering::SeqCst) {
+        return;
+    }
+    FAULT_CAUSE.store(mcause::read().code(), Ordering::Relaxed);
+    FAULT_PC.store(mepc::read(), Ordering::Relaxed);
+    FAULT_TVAL.store(mtval::read(), Ordering::Relaxed);
+    FAULTED.store(true, Ordering::SeqCst);
+    // SAFETY: the faulting code is abandoned.
+    unsafe { resume_runner() };
+}
+
+/// Make `mret` continue at `__hpm_test_resume` in the runner's mode, with
+/// the runner's `mstatus.MIE`, and complete any PLIC claim of an abandoned
+/// interrupt handler.
+///
+/// The trap may be nested in an interrupt or exception handler, whose own
+/// `mret` never runs.
+unsafe fn resume_runner() {
+    extern "C" {
+        static _sstack: u8;
+    }
+
+    complete_claims();
+    mepc::write(__hpm_test_resume as *const () as usize);
+    if RUNNER_IN_M.load(Ordering::Relaxed) {
+        let mut status = mstatus::read();
+        status.set_mpp(mstatus::MPP::Machine);
+        status.set_mpie(RUNNER_MIE.load(Ordering::Relaxed));
+        mstatus::write(status);
+    } else {
+        mstatus::set_mpp(mstatus::MPP::User);
+        mstatus::set_mpie();
+        // `CORE_LOCAL` only restores the trap stack for traps from U-mode
+        mscratch::write(core::ptr::addr_of!(_sstack) as usize);
+    }
+}
+
+/// Complete every enabled source of the M-mode PLIC context. Completing a
+/// source that is not claimed has no effect.
+unsafe fn complete_claims() {
+    let plic = Plic::from_ptr(hpm_riscv_rt::PLIC_BASE as *mut ());
+    for word in 0..4 {
+        let enabled = plic.targetint(0).inten(word).read().0;
+        for bit in (0..32).filter(|bit| enabled & (1 << bit) != 0) {
+            // A plain write: reading the claim register would claim
+            plic.targetconfig(0)
+                .claim()
+                .write(|w| w.set_interrupt_id((word * 32 + bit) as u16));
+        }
+    }
+}
@@ -0,0 +1,110 @@
+//! Minimal RTT control block with one up channel (`rtt` feature).
+//!
+//! The block goes in the runtime's `.rtt` section and the buffer in
+//! `.rtt.buffer`, so it cannot be combined with defmt-rtt or rtt-target in
+//! the same binary. Writes block while the buffer is full, so the host must
+//! be attached.
+
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+const BUFFER_SIZE: usize = 1024;
+
+/// `SEGGER_RTT_MODE_BLOCK_IF_FIFO_FULL`
+const MODE_BLOCK_IF_FULL: usize = 2;
+
+#[repr(C)]
+struct Channel {
+    name: *const u8,
+    buffer: *mut u8,
+    size: usize,
+    write: AtomicUsize,
+    read: AtomicUsize,
+    flags: usize,
+}
+
+#[repr(C)]
+struct ControlBlock {
+    id: [u8; 16],
+    max_up: usize,
+    max_down: usize,
+    up: Channel,
+}
+
+struct Block(UnsafeCell<ControlBlock>);
+
+// SAFETY: only the runner writes, the host reads.
+unsafe impl Sync for Block {}
+
+struct Buffer(UnsafeCell<[u8; BUFFER_SIZE]>);
+
+// SAFETY: see `Block`.
+unsafe impl Sync for Buffer {}
+
+#[link_section = ".rtt.buffer"]
+static BUFFER: Buffer = Buffer(UnsafeCell::new([0; BUFFER_SIZE]));
+
+#[no_mangle]
+#[link_section = ".rtt"]
+static _SEGGER_RTT: Block = Block(UnsafeCell::new(ControlBlock {
+    // Written by `init`, so the host does not find the block in flash
+    id: [0; 16],
+    max_up: 1,
+    max_down: 0,
+    up: Channel {
+        name: c"Terminal".as_ptr().cast(),
+        buffer: BUFFER.0.get() as *mut u8,
+        size: BUFFER_SIZE,
+        write: AtomicUsize::new(0),
+        read: AtomicUsize::new(0),
+        flags: MODE_BLOCK_IF_FULL,
+    },
+}));
+
+/// Publish the control block.
+pub(crate) fn init() {
+    let block = _SEGGER_RTT.0.get();
+    // SAFETY: the host only reads the block once the id is complete.
+    unsafe {
+        core::ptr::addr_of_mut!((*block).id)
+            .cast::<u8>()
+            .add(1)
+            .copy_from_nonoverlapping(b"EGGER RTT".as_ptr(), 9);
+        core::sync::atomic::fence(Ordering::SeqCst);
+        core::ptr::addr_of_mut!((*block).id)
+            .cast::<u8>()
+            .write_volatile(b'S');
+    }
+}
+
+/// Write `bytes` to the up channel, waiting for the host when it is full.
+pub(crate) fn write(mut bytes: &[u8]) {
+    // SAFETY: the runner is the only writer.
+    let channel = unsafe { &(*_SEGGER_RTT.0.get()).up };
+    let mut write = channel.write.load(Ordering::Relaxed);
+    while !bytes.is_empty() {
+        let read = channel.read.load(Ordering::Acquire);
+        // Keep one byte free to tell a full buffer from an empty one
+        let free = if read > write {
+            read - write - 1
+        } else if read == 0 {
+            BUFFER_SIZE - write - 1
+        } else {
+            BUFFER_SIZE - write
+        };
+        if free == 0 {
+            core::hint::spin_loop();
+            continue;
+        }
+        let len = free.min(bytes.len());
+        // SAFETY: `write..write + len` is free space in the buffer.
+        unsafe {
+            (BUFFER.0.get() as *mut u8)
+                .add(write)
+                .copy_from_nonoverlapping(bytes.as_ptr(), len);
+        }
+        write = (write + len) % BUFFER_SIZE;
+        channel.write.store(write, Ordering::Release);
+        bytes = &bytes[len..];
+    }
+}
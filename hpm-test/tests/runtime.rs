@@ -0,0 +1,195 @@
+//! Runtime tests on QEMU `virt`: exception handlers, `#[fast]` placement
+//! and PLIC interrupt dispatch, through the harness.
+//!
+//! ```text
+//! cargo test -p hpm-test --features emulator --no-run
+//! qemu-system-riscv32 -M virt -cpu rv32 -bios none -nographic -semihosting \
+//!     -kernel target/riscv32imafc-unknown-none-elf/debug/deps/runtime-<hash>
+//! ```
+//!
+//! The interrupt source is virt's 16550 UART, which raises PLIC interrupt
+//! 10 as soon as its transmitter-empty interrupt is enabled.
+
+#![no_std]
+#![no_main]
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use andes_riscv::plic::{Plic, PlicExt};
+use hpm_riscv_rt::TrapFrame;
+
+/// PLIC interrupt of virt's UART0.
+const UART0_IRQ: usize = 10;
+/// Interrupt enable register of virt's UART0.
+const UART0_IER: *mut u8 = 0x1000_0001 as *mut u8;
+/// Not decoded by virt: loads fault.
+const UNMAPPED: usize = 0x1800_0000;
+
+static UART0_COUNT: AtomicUsize = AtomicUsize::new(0);
+static UART0_PANIC: AtomicBool = AtomicBool::new(false);
+static ILLEGAL_PANIC: AtomicBool = AtomicBool::new(false);
+
+extern "C" {
+    fn UART0();
+}
+
+/// PLIC vector table: entry N holds the handler of interrupt N, entry 0 is
+/// not read in direct mode.
+#[link_section = ".vector_table.interrupts"]
+#[no_mangle]
+#[used]
+static __INTERRUPTS: [Option<unsafe extern "C" fn()>; UART0_IRQ + 1] = {
+    let mut table: [Option<unsafe extern "C" fn()>; UART0_IRQ + 1] = [None; UART0_IRQ + 1];
+    table[UART0_IRQ] = Some(UART0);
+    table
+};
+
+// Vector table handlers return with `mret`. `#[external_interrupt]` needs
+// the unstable `riscv-interrupt-m` ABI, so wrap a plain function instead.
+core::arch::global_asm!(
+    ".section .text.UART0, \"ax\"",
+    ".global UART0",
+    ".balign 4",
+    "UART0:",
+    "call {handler}",
+    "mret",
+    handler = sym uart0,
+);
+
+extern "C" fn uart0() {
+    // Transmitter-empty stays pending until disabled
+    // SAFETY: virt's UART0 is not used otherwise, output goes to semihosting
+    unsafe { UART0_IER.write_volatile(0) };
+    UART0_COUNT.fetch_add(1, Ordering::SeqCst);
+    if UART0_PANIC.swap(false, Ordering::SeqCst) {
+        panic!("in the UART0 handler");
+    }
+}
+
+/// Runs before `ExceptionHandler`, which the harness owns.
+#[no_mangle]
+extern "C" fn IllegalInstruction(_trap_frame: &TrapFrame) {
+    if ILLEGAL_PANIC.swap(false, Ordering::SeqCst) {
+        panic!("in the IllegalInstruction handler");
+    }
+}
+
+/// Raise UART0's interrupt and wait for its handler; returns whether it ran
+/// exactly once.
+fn raise_uart0() -> bool {
+    let before = UART0_COUNT.load(Ordering::SeqCst);
+    // SAFETY: virt's UART0 is not used otherwise, output goes to semihosting
+    unsafe { UART0_IER.write_volatile(0x02) };
+    for _ in 0..100_000 {
+        if UART0_COUNT.load(Ordering::SeqCst) != before {
+            break;
+        }
+    }
+    UART0_COUNT.load(Ordering::SeqCst) == before + 1
+}
+
+#[hpm_test::tests]
+mod tests {
+    use core::ptr::addr_of;
+    use core::sync::atomic::Ordering;
+
+    use super::*;
+    use hpm_riscv_rt::fast;
+    use riscv::register::mstatus;
+
+    extern "C" {
+        static __fast_text_start__: u8;
+        static __fast_text_end__: u8;
+        static __fast_data_start__: u8;
+        static __fast_data_end__: u8;
+        static __fast_bss_start__: u8;
+        static __fast_bss_end__: u8;
+    }
+
+    #[fast]
+    fn in_ilm(x: u32) -> u32 {
+        x + 1
+    }
+
+    #[fast]
+    static TABLE: [u32; 4] = [1, 2, 3, 4];
+
+    #[fast]
+    static mut SCRATCH: [u32; 8] = [0; 8];
+
+    #[init]
+    fn init() {
+        // SAFETY: enables only UART0's source, priority 1 over threshold 0
+        let plic = unsafe { Plic::from_ptr(hpm_riscv_rt::PLIC_BASE as *mut ()) };
+        plic.priority(UART0_IRQ).write(|w| w.set_priority(1));
+        plic.targetint(0)
+            .inten(UART0_IRQ / 32)
+            .modify(|w| w.set_interrupt(w.interrupt() | 1 << (UART0_IRQ % 32)));
+        plic.set_threshold(0);
+    }
+
+    #[test]
+    fn fast_text_in_ilm() {
+        let addr = in_ilm as *const () as usize;
+        let range = addr_of!(__fast_text_start__) as usize..addr_of!(__fast_text_end__) as usize;
+        assert!(range.contains(&addr), "{:#x} not in {:#x?}", addr, range);
+        assert_eq!(in_ilm(1), 2);
+    }
+
+    #[test]
+    fn fast_data_copied() {
+        let addr = addr_of!(TABLE) as usize;
+        let range = addr_of!(__fast_data_start__) as usize..addr_of!(__fast_data_end__) as usize;
+        assert!(range.contains(&addr), "{:#x} not in {:#x?}", addr, range);
+        assert_eq!(TABLE, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn fast_bss_zeroed() {
+        let addr = addr_of!(SCRATCH) as usize;
+        let range = addr_of!(__fast_bss_start__) as usize..addr_of!(__fast_bss_end__) as usize;
+        assert!(range.contains(&addr), "{:#x} not in {:#x?}", addr, range);
+        assert_eq!(unsafe { addr_of!(SCRATCH).read_volatile() }, [0; 8]);
+    }
+
+    #[test]
+    #[should_fault(LoadFault)]
+    fn load_fault() {
+        unsafe { (UNMAPPED as *const u32).read_volatile() };
+    }
+
+    #[test]
+    #[should_fault(IllegalInstruction)]
+    fn illegal_instruction() {
+        unsafe { core::arch::asm!("unimp") };
+    }
+
+    /// Passes only if the exception-specific handler runs, since
+    /// `ExceptionHandler` alone would report a fault.
+    #[test]
+    #[should_panic]
+    fn specific_handler_runs() {
+        ILLEGAL_PANIC.store(true, Ordering::SeqCst);
+        unsafe { core::arch::asm!("unimp") };
+    }
+
+    #[test]
+    fn interrupt_dispatch() {
+        assert!(raise_uart0());
+    }
+
+    #[test]
+    #[should_panic]
+    fn panic_in_interrupt() {
+        UART0_PANIC.store(true, Ordering::SeqCst);
+        raise_uart0();
+    }
+
+    /// Interrupts still work after `panic_in_interrupt`: the harness left
+    /// the handler with interrupts enabled and completed its claim.
+    #[test]
+    fn interrupt_after_panic() {
+        assert!(mstatus::read().mie());
+        assert!(raise_uart0());
+    }
+}
@@ -3,12 +3,30 @@
 //! This crate provides:
 //! - `#[entry]` - Define the program entry point
 //! - `#[pre_init]` - Define a pre-initialization function
-//! - `#[fast]` - Place functions/statics in ILM/DLM
+//! - `#[sdram_init]` - Define the SDRAM initialization function
+//! - `#[oom_handler]` - Define the heap allocation failure hook
+//! - `#[startup_hook]` - Define a hook for a phase of `_hpm_start_rust`
+//! - `#[init]` - Register one of several ordered init functions
+//! - `#[fast]` - Place functions/impl blocks/statics in ILM/DLM
+//! - `#[sdram]` - Place functions/statics in external SDRAM
+//! - `#[noinit]` - Place statics in persistent RAM that is never initialized
+//! - `#[noncacheable]` - Place statics in non-cacheable RAM
+//! - `#[ahb_sram]` - Place statics in AHB SRAM
+//! - `#[init_section]` - Place statics in a user section initialized at startup
 //! - `#[external_interrupt]` - Define PLIC external interrupt handlers
+//! - `#[syscall]` - Register a U-mode `ecall` handler
 
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{parse_macro_input, spanned::Spanned, Expr, Item, ItemFn, parse::Parse, parse::ParseStream};
+use syn::{
+    parse::Parse,
+    parse::ParseStream,
+    parse_macro_input,
+    punctuated::Punctuated,
+    spanned::Spanned,
+    visit_mut::{self, VisitMut},
+    Expr, Item, ItemFn,
+};
 
 /// Attribute to declare the entry point of the program.
 ///
@@ -72,10 +90,268 @@ pub fn pre_init(_args: TokenStream, input: TokenStream) -> TokenStream {
     .into()
 }
 
-/// Place a function or static into fast memory (ILM/DLM).
+/// Attribute to declare the SDRAM initialization function.
+///
+/// The function must have the signature `unsafe fn()`.
+/// It is called from `_hpm_start_rust` before the `.sdram.*` sections are
+/// initialized, and must configure clocks and the FEMC controller.
+/// At this point:
+/// - .data and .bss are initialized
+/// - Caches are disabled
+/// - Interrupts are disabled
+///
+/// # Example
+///
+/// ```ignore
+/// #[sdram_init]
+/// unsafe fn setup_sdram() {
+///     // Configure FEMC for the board's SDRAM
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn sdram_init(_args: TokenStream, input: TokenStream) -> TokenStream {
+    let f = parse_macro_input!(input as ItemFn);
+
+    let fn_attrs = &f.attrs;
+    let fn_vis = &f.vis;
+    let fn_sig = &f.sig;
+    let fn_block = &f.block;
+
+    quote!(
+        #(#fn_attrs)*
+        #[unsafe(export_name = "__sdram_init")]
+        #fn_vis #fn_sig #fn_block
+    )
+    .into()
+}
+
+/// Attribute to declare the heap allocation failure hook (`alloc` feature).
+///
+/// The function must have the signature `fn(core::alloc::Layout)`.
+/// It is called with the failed layout when the global allocator runs out of
+/// memory, before the allocation error is raised. It may log, reset or
+/// diverge.
+///
+/// # Example
+///
+/// ```ignore
+/// #[oom_handler]
+/// fn oom(layout: core::alloc::Layout) {
+///     defmt::error!("out of heap: {} bytes", layout.size());
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn oom_handler(_args: TokenStream, input: TokenStream) -> TokenStream {
+    let f = parse_macro_input!(input as ItemFn);
+
+    let fn_attrs = &f.attrs;
+    let fn_vis = &f.vis;
+    let fn_sig = &f.sig;
+    let fn_block = &f.block;
+
+    quote!(
+        #(#fn_attrs)*
+        #[unsafe(export_name = "__hpm_oom")]
+        #fn_vis #fn_sig #fn_block
+    )
+    .into()
+}
+
+/// Attribute to declare a hook for one phase of `_hpm_start_rust`.
+///
+/// Phases, in the order they run:
+/// - `after_ram_init`: all RAM sections, including `.sdram.*`, are
+///   initialized; caches are disabled
+/// - `before_cache_enable`: `fn(&mut hpm_riscv_rt::cache::Caches)`, may
+///   clear `icache`/`dcache` to keep a cache disabled
+/// - `after_cache_enable`: caches are enabled, PMA and PMP are not yet set up
+/// - `before_interrupts`: PMA, PMP and the heap are set up, the PLIC and
+///   `mtvec` are not yet touched, e.g. to configure clocks
+///
+/// Hooks other than `before_cache_enable` have the signature `unsafe fn()`.
+/// Interrupts are disabled in all phases. Each phase takes one hook.
+///
+/// # Example
+///
+/// ```ignore
+/// #[startup_hook(before_interrupts)]
+/// unsafe fn clocks() {
+///     // Switch the CPU to PLL0 before peripherals are set up
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn startup_hook(args: TokenStream, input: TokenStream) -> TokenStream {
+    let phase = parse_macro_input!(args as syn::Ident);
+    let f = parse_macro_input!(input as ItemFn);
+
+    let symbol = match phase.to_string().as_str() {
+        "after_ram_init" => "__hpm_after_ram_init",
+        "before_cache_enable" => "__hpm_before_cache_enable",
+        "after_cache_enable" => "__hpm_after_cache_enable",
+        "before_interrupts" => "__hpm_before_interrupts",
+        _ => {
+            return syn::Error::new(
+                phase.span(),
+                "unknown phase, expected `after_ram_init`, `before_cache_enable`, \
+                 `after_cache_enable` or `before_interrupts`",
+            )
+            .to_compile_error()
+            .into();
+        }
+    };
+    if let Err(e) = reject_generic_fn(&f.sig, "#[startup_hook]") {
+        return e.to_compile_error().into();
+    }
+
+    let fn_attrs = &f.attrs;
+    let fn_vis = &f.vis;
+    let fn_sig = &f.sig;
+    let fn_block = &f.block;
+
+    quote!(
+        #(#fn_attrs)*
+        #[unsafe(export_name = #symbol)]
+        #fn_vis #fn_sig #fn_block
+    )
+    .into()
+}
+
+/// Arguments for the init attribute: `stage = <stage>, priority = N`.
+struct InitArgs {
+    stage: syn::Ident,
+    priority: u16,
+}
+
+impl Parse for InitArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut stage = None;
+        let mut priority = None;
+        for meta in Punctuated::<syn::MetaNameValue, syn::Token![,]>::parse_terminated(input)? {
+            if meta.path.is_ident("stage") {
+                let Expr::Path(path) = &meta.value else {
+                    return Err(syn::Error::new(meta.value.span(), "expected a stage"));
+                };
+                let ident = path.path.require_ident()?;
+                if !["pre_ram", "post_ram", "pre_main"].contains(&ident.to_string().as_str()) {
+                    return Err(syn::Error::new(
+                        ident.span(),
+                        "unknown stage, expected `pre_ram`, `post_ram` or `pre_main`",
+                    ));
+                }
+                stage = Some(ident.clone());
+            } else if meta.path.is_ident("priority") {
+                let Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Int(lit),
+                    ..
+                }) = &meta.value
+                else {
+                    return Err(syn::Error::new(meta.value.span(), "expected an integer"));
+                };
+                priority = Some(lit.base10_parse()?);
+            } else {
+                return Err(syn::Error::new(meta.path.span(), "unknown argument"));
+            }
+        }
+        match (stage, priority) {
+            (Some(stage), Some(priority)) => Ok(InitArgs { stage, priority }),
+            _ => Err(input.error("expected `stage = ..., priority = N`")),
+        }
+    }
+}
+
+/// Register a function to run at a startup stage, ordered by priority.
+///
+/// Unlike `#[pre_init]`, any number of crates can register init functions.
+/// The function must have the signature `unsafe fn()` or `fn()`.
+///
+/// Stages:
+/// - `pre_ram`: after `__pre_init`, before `.data`/`.bss` are initialized.
+///   Statics must not be used, so the function must be an `unsafe fn()`.
+/// - `post_ram`: after RAM is initialized, before caches are enabled
+/// - `pre_main`: after interrupts are set up, right before `main`
+///
+/// Within a stage, lower priorities (`0..=65535`) run first. Two functions
+/// with the same stage and priority are a duplicate symbol error at link
+/// time.
+///
+/// # Example
+///
+/// ```ignore
+/// #[init(stage = pre_ram, priority = 10)]
+/// unsafe fn disable_watchdog() {
+///     // Before the (slow) RAM init
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn init(args: TokenStream, input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as InitArgs);
+    let f = parse_macro_input!(input as ItemFn);
+
+    if let Err(e) = reject_generic_fn(&f.sig, "#[init]") {
+        return e.to_compile_error().into();
+    }
+    if !f.sig.inputs.is_empty() {
+        return syn::Error::new(f.sig.inputs.span(), "#[init] functions take no arguments")
+            .to_compile_error()
+            .into();
+    }
+    if args.stage == "pre_ram" && f.sig.unsafety.is_none() {
+        return syn::Error::new(
+            f.sig.fn_token.span(),
+            "`pre_ram` init functions run before RAM is initialized and must be `unsafe fn`",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    let fn_name = &f.sig.ident;
+    let call = if f.sig.unsafety.is_some() {
+        quote!(unsafe { #fn_name() })
+    } else {
+        quote!(#fn_name())
+    };
+    // Zero-padded, so sorting by name sorts by priority
+    let section = format!(".hpm_init.{}.{:05}", args.stage, args.priority);
+    let export_name = format!("__hpm_init_{}_{}", args.stage, args.priority);
+
+    quote!(
+        #f
+
+        const _: () = {
+            unsafe extern "C" fn trampoline() {
+                #call;
+            }
+
+            #[used]
+            #[unsafe(link_section = #section)]
+            #[unsafe(export_name = #export_name)]
+            static ENTRY: ::hpm_riscv_rt::startup::InitFn = trampoline;
+        };
+    )
+    .into()
+}
+
+/// Place a function, impl block or static into fast memory (ILM/DLM).
 ///
 /// Functions are placed into `.fast.text` section (ILM).
+/// On an `impl` block, every method is placed into `.fast.text`.
+/// Closures passed as call arguments inside a fast function are marked
+/// `#[inline(always)]` so they are inlined into it rather than left in flash.
+///
 /// Statics are placed into `.fast.data` or `.fast.bss` section (DLM).
+/// Statics initialized with `MaybeUninit::uninit()`, `ptr::null()` or an
+/// all-zero literal (`0`, `[0; N]`, ...) go to `.fast.bss`, others to
+/// `.fast.data`. Constructors such as `AtomicU32::new(0)` are not trusted to
+/// be all-zero, so give those `bss` explicitly. The section can be chosen
+/// explicitly:
+/// - `#[fast(bss)]`: `.fast.bss`, zeroed at boot. The initializer must be
+///   all-zero bytes; visibly non-zero literals, arrays, tuples and struct
+///   literals are rejected, other expressions are not checked
+/// - `#[fast(data)]`: `.fast.data`, copied from flash at boot
+/// - `#[fast(rodata)]`: `.fast.rodata`, lookup tables copied into DLM at boot
+/// - `#[fast(text)]`: functions and impl blocks (default)
+///
+/// Generic functions and `const` items are rejected at compile time.
 ///
 /// # Example
 ///
@@ -88,53 +364,697 @@ pub fn pre_init(_args: TokenStream, input: TokenStream) -> TokenStream {
 /// }
 ///
 /// #[fast]
-/// static BUFFER: [u8; 1024] = [0; 1024];
+/// static mut BUFFER: [u8; 1024] = [0; 1024]; // .fast.bss
+///
+/// #[fast(rodata)]
+/// static SINE: [i16; 256] = make_sine_table();
 /// ```
 #[proc_macro_attribute]
-pub fn fast(_args: TokenStream, input: TokenStream) -> TokenStream {
+pub fn fast(args: TokenStream, input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as PlacementArgs);
     let item = parse_macro_input!(input as Item);
 
+    match fast_item(&args, item) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn fast_item(args: &PlacementArgs, item: Item) -> syn::Result<proc_macro2::TokenStream> {
     match item {
-        Item::Fn(f) => {
-            quote!(
+        Item::Fn(mut f) => {
+            args.expect_code("#[fast]")?;
+            reject_generic_fn(&f.sig, "#[fast]")?;
+            FastBody.visit_block_mut(&mut f.block);
+
+            Ok(quote!(
                 #[unsafe(link_section = ".fast.text")]
                 #[inline(never)]
                 #f
-            )
-            .into()
+            ))
         }
-        Item::Static(item) => {
-            // Check if it's uninitialized (MaybeUninit::uninit())
-            let section = if is_uninit_expr(&item.expr) {
-                quote!(#[unsafe(link_section = ".fast.bss")])
-            } else {
-                quote!(#[unsafe(link_section = ".fast.data")])
+        Item::Impl(mut imp) => {
+            args.expect_code("#[fast]")?;
+            if let Some(param) = imp.generics.type_params().next() {
+                return Err(syn::Error::new(param.span(), "#[fast] cannot be applied to generic impl blocks"));
+            }
+            if let Some(param) = imp.generics.const_params().next() {
+                return Err(syn::Error::new(param.span(), "#[fast] cannot be applied to generic impl blocks"));
+            }
+            for impl_item in &mut imp.items {
+                if let syn::ImplItem::Fn(f) = impl_item {
+                    reject_generic_fn(&f.sig, "#[fast]")?;
+                    FastBody.visit_block_mut(&mut f.block);
+                    f.attrs.push(syn::parse_quote!(#[unsafe(link_section = ".fast.text")]));
+                    f.attrs.push(syn::parse_quote!(#[inline(never)]));
+                }
+            }
+
+            Ok(quote!(#imp))
+        }
+        Item::Static(mut item) => {
+            let section = match args.static_kind(&item, "#[fast]")? {
+                SectionKind::Data => ".fast.data",
+                SectionKind::Bss => ".fast.bss",
+                SectionKind::Rodata => ".fast.rodata",
+                SectionKind::Text => unreachable!(),
             };
+            if let Some(align) = args.align {
+                align_static(&mut item, align);
+            }
 
-            quote!(
-                #section
+            Ok(quote!(
+                #[unsafe(link_section = #section)]
                 #item
-            )
-            .into()
+            ))
+        }
+        Item::Const(c) => Err(syn::Error::new(
+            c.span(),
+            "#[fast] cannot be applied to `const` items, which have no fixed address; use a `static`",
+        )),
+        _ => Err(syn::Error::new(
+            item.span(),
+            "#[fast] can only be applied to functions, impl blocks or statics",
+        )),
+    }
+}
+
+/// Reject functions that would be monomorphized outside the placed section.
+fn reject_generic_fn(sig: &syn::Signature, attr: &str) -> syn::Result<()> {
+    if let Some(param) = sig
+        .generics
+        .params
+        .iter()
+        .find(|p| !matches!(p, syn::GenericParam::Lifetime(_)))
+    {
+        return Err(syn::Error::new(
+            param.span(),
+            format!("{attr} cannot be applied to generic functions"),
+        ));
+    }
+    for input in &sig.inputs {
+        if let syn::FnArg::Typed(arg) = input {
+            if let syn::Type::ImplTrait(ty) = &*arg.ty {
+                return Err(syn::Error::new(
+                    ty.span(),
+                    format!("{attr} cannot be applied to generic functions"),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Rewrites the body of a fast function: closures passed as call arguments
+/// are force-inlined, nested non-generic functions are placed in `.fast.text`.
+struct FastBody;
+
+impl FastBody {
+    fn inline_closure(arg: &mut Expr) {
+        if let Expr::Closure(closure) = arg {
+            if !closure.attrs.iter().any(|a| a.path().is_ident("inline")) {
+                closure.attrs.push(syn::parse_quote!(#[inline(always)]));
+            }
+        }
+    }
+}
+
+impl VisitMut for FastBody {
+    fn visit_expr_call_mut(&mut self, call: &mut syn::ExprCall) {
+        call.args.iter_mut().for_each(Self::inline_closure);
+        visit_mut::visit_expr_call_mut(self, call);
+    }
+
+    fn visit_expr_method_call_mut(&mut self, call: &mut syn::ExprMethodCall) {
+        call.args.iter_mut().for_each(Self::inline_closure);
+        visit_mut::visit_expr_method_call_mut(self, call);
+    }
+
+    fn visit_item_fn_mut(&mut self, f: &mut ItemFn) {
+        if reject_generic_fn(&f.sig, "").is_ok() {
+            f.attrs
+                .push(syn::parse_quote!(#[unsafe(link_section = ".fast.text")]));
+        }
+        visit_mut::visit_item_fn_mut(self, f);
+    }
+}
+
+/// Place a function or static into external SDRAM (requires `hpm-sdram.x`).
+///
+/// Functions are placed into `.sdram.text`.
+/// Statics are placed into `.sdram.data` or `.sdram.bss`, using the same
+/// classification and `bss`/`data` arguments as `#[fast]`.
+/// With `#[sdram(noncacheable)]`, statics are placed into
+/// `.sdram.noncacheable.data` or `.sdram.noncacheable.bss`
+/// (`REGION_SDRAM_NONCACHEABLE`), which is made non-cacheable by the
+/// `pma-noncacheable` feature.
+///
+/// # Example
+///
+/// ```ignore
+/// use hpm_riscv_rt::sdram;
+///
+/// #[sdram]
+/// static mut FRAME_BUFFER: [u16; 800 * 480] = [0; 800 * 480];
+///
+/// #[sdram(noncacheable)]
+/// static mut DMA_BUFFER: MaybeUninit<[u8; 65536]> = MaybeUninit::uninit();
+/// ```
+#[proc_macro_attribute]
+pub fn sdram(args: TokenStream, input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as PlacementArgs);
+    let item = parse_macro_input!(input as Item);
+
+    match sdram_item(&args, item) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn sdram_item(args: &PlacementArgs, item: Item) -> syn::Result<proc_macro2::TokenStream> {
+    match item {
+        Item::Fn(f) if !args.noncacheable => {
+            args.expect_code("#[sdram]")?;
+            reject_generic_fn(&f.sig, "#[sdram]")?;
+
+            Ok(quote!(
+                #[unsafe(link_section = ".sdram.text")]
+                #[inline(never)]
+                #f
+            ))
+        }
+        Item::Static(mut item) => {
+            let kind = args.static_kind(&item, "#[sdram]")?;
+            let section = match (args.noncacheable, kind) {
+                (false, SectionKind::Bss) => ".sdram.bss",
+                (false, _) => ".sdram.data",
+                (true, SectionKind::Bss) => ".sdram.noncacheable.bss",
+                (true, _) => ".sdram.noncacheable.data",
+            };
+            if let Some(align) = args.align {
+                align_static(&mut item, align);
+            }
+
+            Ok(quote!(
+                #[unsafe(link_section = #section)]
+                #item
+            ))
         }
         _ => {
-            let span = item.span();
-            syn::Error::new(span, "#[fast] can only be applied to functions or statics")
-                .to_compile_error()
-                .into()
+            let msg = if args.noncacheable {
+                "#[sdram(noncacheable)] can only be applied to statics"
+            } else {
+                "#[sdram] can only be applied to functions or statics"
+            };
+            Err(syn::Error::new(item.span(), msg))
+        }
+    }
+}
+
+/// Place a static into the `.noinit` section (`REGION_NOINIT`).
+///
+/// The section is never copied or zeroed by the startup code, so the static
+/// keeps its contents across warm resets. The type must be `MaybeUninit<T>`
+/// or `Persistent<T>`, since the contents are unknown after power-on.
+///
+/// # Example
+///
+/// ```ignore
+/// use hpm_riscv_rt::{noinit, noinit::Persistent};
+///
+/// #[noinit]
+/// static mut RESET_REASON: Persistent<u32> = Persistent::uninit();
+///
+/// #[noinit]
+/// static mut SCRATCH: MaybeUninit<[u8; 256]> = MaybeUninit::uninit();
+/// ```
+#[proc_macro_attribute]
+pub fn noinit(_args: TokenStream, input: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(input as Item);
+
+    let Item::Static(item) = item else {
+        return syn::Error::new(item.span(), "#[noinit] can only be applied to statics")
+            .to_compile_error()
+            .into();
+    };
+
+    let ty_name = match &*item.ty {
+        syn::Type::Path(p) => p.path.segments.last().map(|s| s.ident.to_string()),
+        _ => None,
+    };
+    if !matches!(ty_name.as_deref(), Some("MaybeUninit" | "Persistent")) {
+        return syn::Error::new(
+            item.ty.span(),
+            "#[noinit] statics must have type `MaybeUninit<T>` or `Persistent<T>`",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    quote!(
+        #[unsafe(link_section = ".noinit")]
+        #item
+    )
+    .into()
+}
+
+/// Whether a static initializer is all-zero or uninitialized, i.e. belongs in `.bss`.
+///
+/// Works on the syntax tree, so it cannot see through `const` items or
+/// function calls; use an explicit `bss`/`data` argument in those cases.
+fn is_bss_expr(expr: &Expr) -> bool {
+    match expr {
+        // MaybeUninit::uninit(), core::ptr::null(), ... Other constructors,
+        // including atomics and cells, may be user types with the same name
+        // and stay in `.data`
+        Expr::Call(call) if call.args.is_empty() => {
+            is_std_path(
+                &call.func,
+                &["mem", "MaybeUninit"],
+                &["uninit", "uninit_array"],
+            ) || is_std_path(&call.func, &["ptr"], &["null", "null_mut"])
+        }
+        Expr::Lit(lit) => match &lit.lit {
+            syn::Lit::Int(i) => i.base10_digits().bytes().all(|b| b == b'0'),
+            syn::Lit::Float(f) => f.base10_digits().bytes().all(|b| matches!(b, b'0' | b'.')),
+            syn::Lit::Bool(b) => !b.value,
+            _ => false,
+        },
+        Expr::Repeat(r) => is_bss_expr(&r.expr),
+        Expr::Array(a) => a.elems.iter().all(is_bss_expr),
+        Expr::Tuple(t) => t.elems.iter().all(is_bss_expr),
+        // A struct literal may be an enum variant with a non-zero discriminant,
+        // which the syntax cannot tell apart, so it stays in `.data`
+        Expr::Cast(c) => is_bss_expr(&c.expr),
+        Expr::Paren(p) => is_bss_expr(&p.expr),
+        Expr::Group(g) => is_bss_expr(&g.expr),
+        _ => false,
+    }
+}
+
+/// Whether a static initializer is visibly non-zero: a literal, or an array,
+/// tuple or struct literal containing one.
+///
+/// Anything else (constants, function calls) is trusted to be all-zero bytes
+/// when `bss` is given explicitly.
+fn is_nonzero_expr(expr: &Expr) -> bool {
+    match expr {
+        Expr::Lit(_) => !is_bss_expr(expr),
+        Expr::Repeat(r) => is_nonzero_expr(&r.expr),
+        Expr::Array(a) => a.elems.iter().any(is_nonzero_expr),
+        Expr::Tuple(t) => t.elems.iter().any(is_nonzero_expr),
+        Expr::Struct(st) => st.fields.iter().any(|f| is_nonzero_expr(&f.expr)),
+        Expr::Cast(c) => is_nonzero_expr(&c.expr),
+        Expr::Paren(p) => is_nonzero_expr(&p.expr),
+        Expr::Group(g) => is_nonzero_expr(&g.expr),
+        _ => false,
+    }
+}
+
+/// Reject an explicit `bss` on a static whose initializer would be lost by zeroing.
+fn check_bss_expr(expr: &Expr) -> syn::Result<()> {
+    if is_nonzero_expr(expr) {
+        return Err(syn::Error::new(
+            expr.span(),
+            "`bss` statics are zeroed at boot, but this initializer is not all zeros; use `data`",
+        ));
+    }
+    Ok(())
+}
+
+/// Whether `expr` is the path of one of `names` in the `core`/`std` module
+/// `module`: `core::ptr::null`, `ptr::null`, `mem::MaybeUninit::uninit` or
+/// `MaybeUninit::uninit`, but not `null` alone.
+fn is_std_path(expr: &Expr, module: &[&str], names: &[&str]) -> bool {
+    let Expr::Path(p) = expr else {
+        return false;
+    };
+    if p.qself.is_some() {
+        return false;
+    }
+    let segments: Vec<String> = p
+        .path
+        .segments
+        .iter()
+        .map(|s| s.ident.to_string())
+        .collect();
+    let Some((name, prefix)) = segments.split_last() else {
+        return false;
+    };
+    if !names.contains(&name.as_str()) {
+        return false;
+    }
+    match prefix.split_first() {
+        Some((root, rest)) if root == "core" || root == "std" => rest == module,
+        _ => {
+            !prefix.is_empty()
+                && prefix.len() <= module.len()
+                && prefix
+                    .iter()
+                    .rev()
+                    .zip(module.iter().rev())
+                    .all(|(a, b)| a == b)
         }
     }
 }
 
-fn is_uninit_expr(expr: &Expr) -> bool {
-    if let Expr::Call(call) = expr {
-        let s = quote!(#call).to_string();
-        s.contains("MaybeUninit") && (s.contains("uninit()") || s.contains("uninit_array()"))
+/// Arguments for the init_section attribute: `"section"` or `"section", bss`.
+struct InitSectionArgs {
+    section: syn::LitStr,
+    bss: bool,
+}
+
+impl Parse for InitSectionArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let section: syn::LitStr = input.parse()?;
+        let mut bss = false;
+        if input.parse::<Option<syn::Token![,]>>()?.is_some() {
+            let kind: syn::Ident = input.parse()?;
+            bss = match kind.to_string().as_str() {
+                "data" => false,
+                "bss" => true,
+                _ => return Err(syn::Error::new(kind.span(), "expected `data` or `bss`")),
+            };
+        }
+        Ok(InitSectionArgs { section, bss })
+    }
+}
+
+/// Place a static into a user-defined RAM section and register it with the
+/// startup copy/zero tables.
+///
+/// The section must be an output section (usually `NOLOAD`) defined in the
+/// user's linker fragment. With `data` (default), the initializer is kept in
+/// flash and copied at boot. With `bss`, the static is zeroed at boot and its
+/// initializer must be all-zero bytes; a visibly non-zero literal, array,
+/// tuple or struct literal is rejected, anything else is not checked.
+///
+/// # Example
+///
+/// ```ignore
+/// use hpm_riscv_rt::init_section;
+///
+/// #[init_section(".axi_sram")]
+/// static mut TABLE: [u32; 4] = [1, 2, 3, 4];
+///
+/// #[init_section(".axi_sram", bss)]
+/// static mut BUFFER: [u8; 4096] = [0; 4096];
+/// ```
+#[proc_macro_attribute]
+pub fn init_section(args: TokenStream, input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as InitSectionArgs);
+    let item = parse_macro_input!(input as Item);
+
+    let Item::Static(item) = item else {
+        return syn::Error::new(
+            item.span(),
+            "#[init_section] can only be applied to statics",
+        )
+        .to_compile_error()
+        .into();
+    };
+
+    if args.bss {
+        if let Err(e) = check_bss_expr(&item.expr) {
+            return e.to_compile_error().into();
+        }
+    }
+
+    let section = &args.section;
+    let entry = init_table_entry(&item, args.bss);
+
+    quote!(
+        #[unsafe(link_section = #section)]
+        #item
+
+        #entry
+    )
+    .into()
+}
+
+/// Generate the copy table (`bss == false`) or zero table entry for a static.
+fn init_table_entry(item: &syn::ItemStatic, bss: bool) -> proc_macro2::TokenStream {
+    let name = &item.ident;
+    let ty = &item.ty;
+    let expr = &item.expr;
+    let suffix = name.to_string();
+
+    if bss {
+        let entry_section = format!(".zero_table.{suffix}");
+        let entry_name = quote::format_ident!("__HPM_ZERO_ENTRY_{}", name);
+        quote!(
+            #[used]
+            #[doc(hidden)]
+            #[unsafe(link_section = #entry_section)]
+            static #entry_name: ::hpm_riscv_rt::section::ZeroEntry = ::hpm_riscv_rt::section::ZeroEntry {
+                dst: &raw const #name as *mut u8,
+                len: ::core::mem::size_of::<#ty>(),
+            };
+        )
     } else {
-        false
+        let entry_section = format!(".copy_table.{suffix}");
+        let entry_name = quote::format_ident!("__HPM_COPY_ENTRY_{}", name);
+        let image_name = quote::format_ident!("__HPM_INIT_IMAGE_{}", name);
+        quote!(
+            #[doc(hidden)]
+            static #image_name: ::hpm_riscv_rt::section::InitImage<#ty> =
+                ::hpm_riscv_rt::section::InitImage(#expr);
+
+            #[used]
+            #[doc(hidden)]
+            #[unsafe(link_section = #entry_section)]
+            static #entry_name: ::hpm_riscv_rt::section::CopyEntry = ::hpm_riscv_rt::section::CopyEntry {
+                src: &raw const #image_name as *const u8,
+                dst: &raw const #name as *mut u8,
+                len: ::core::mem::size_of::<#ty>(),
+            };
+        )
     }
 }
 
+/// Section flavor selected by a placement attribute argument.
+#[derive(Clone, Copy, PartialEq)]
+enum SectionKind {
+    Text,
+    Data,
+    Bss,
+    Rodata,
+}
+
+/// Arguments shared by the placement attributes:
+/// `text`, `data`, `bss`, `rodata`, `noncacheable` and `align = N`.
+struct PlacementArgs {
+    kind: Option<(SectionKind, proc_macro2::Span)>,
+    noncacheable: bool,
+    align: Option<u32>,
+}
+
+impl Parse for PlacementArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut args = PlacementArgs {
+            kind: None,
+            noncacheable: false,
+            align: None,
+        };
+        for meta in Punctuated::<syn::Meta, syn::Token![,]>::parse_terminated(input)? {
+            match &meta {
+                syn::Meta::NameValue(nv) if nv.path.is_ident("align") => {
+                    let Expr::Lit(syn::ExprLit {
+                        lit: syn::Lit::Int(lit),
+                        ..
+                    }) = &nv.value
+                    else {
+                        return Err(syn::Error::new(nv.value.span(), "expected an integer"));
+                    };
+                    let align: u32 = lit.base10_parse()?;
+                    if !align.is_power_of_two() || !(4..=4096).contains(&align) {
+                        return Err(syn::Error::new(
+                            lit.span(),
+                            "`align` must be a power of two between 4 and 4096",
+                        ));
+                    }
+                    args.align = Some(align);
+                }
+                syn::Meta::Path(path) if path.is_ident("noncacheable") => args.noncacheable = true,
+                syn::Meta::Path(path) => {
+                    let kind = match path.get_ident().map(|i| i.to_string()).as_deref() {
+                        Some("text") => SectionKind::Text,
+                        Some("data") => SectionKind::Data,
+                        Some("bss") => SectionKind::Bss,
+                        Some("rodata") => SectionKind::Rodata,
+                        _ => return Err(syn::Error::new(path.span(), "unknown argument")),
+                    };
+                    if args.kind.is_some() {
+                        return Err(syn::Error::new(path.span(), "section kind specified twice"));
+                    }
+                    args.kind = Some((kind, path.span()));
+                }
+                _ => return Err(syn::Error::new(meta.span(), "unknown argument")),
+            }
+        }
+        Ok(args)
+    }
+}
+
+impl PlacementArgs {
+    /// Check the arguments are valid for a function or impl block.
+    fn expect_code(&self, attr: &str) -> syn::Result<()> {
+        if let Some((kind, span)) = self.kind {
+            if kind != SectionKind::Text {
+                return Err(syn::Error::new(
+                    span,
+                    format!("{attr} on code only accepts `text`"),
+                ));
+            }
+        }
+        if self.align.is_some() {
+            return Err(syn::Error::new(
+                proc_macro2::Span::call_site(),
+                "`align` only applies to statics",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Section kind for a static: explicit argument, or classified from the initializer.
+    /// `rodata` is only accepted by `#[fast]`.
+    fn static_kind(&self, item: &syn::ItemStatic, attr: &str) -> syn::Result<SectionKind> {
+        match self.kind {
+            None if is_bss_expr(&item.expr) => Ok(SectionKind::Bss),
+            None => Ok(SectionKind::Data),
+            Some((SectionKind::Text, span)) => {
+                Err(syn::Error::new(span, "`text` only applies to functions"))
+            }
+            Some((SectionKind::Rodata, span)) if attr != "#[fast]" => Err(syn::Error::new(
+                span,
+                format!("{attr} does not accept `rodata`"),
+            )),
+            Some((SectionKind::Rodata, span))
+                if matches!(item.mutability, syn::StaticMutability::Mut(_)) =>
+            {
+                Err(syn::Error::new(
+                    span,
+                    "`rodata` cannot be used on `static mut`; use `data`",
+                ))
+            }
+            Some((SectionKind::Bss, _)) => check_bss_expr(&item.expr).map(|()| SectionKind::Bss),
+            Some((kind, _)) => Ok(kind),
+        }
+    }
+}
+
+/// Wrap a static's type and initializer in `hpm_riscv_rt::align::Aligned`.
+fn align_static(item: &mut syn::ItemStatic, align: u32) {
+    let marker = quote::format_ident!("A{}", align);
+    let ty = &item.ty;
+    let expr = &item.expr;
+    *item.ty =
+        syn::parse_quote!(::hpm_riscv_rt::align::Aligned<::hpm_riscv_rt::align::#marker, #ty>);
+    *item.expr = syn::parse_quote!(::hpm_riscv_rt::align::Aligned::new(#expr));
+}
+
+/// Place a static into non-cacheable RAM (`REGION_NONCACHEABLE_RAM`).
+///
+/// Statics are placed into `.noncacheable.data` or `.noncacheable.bss`,
+/// using the same classification and `bss`/`data` arguments as `#[fast]`.
+/// Both are initialized at boot.
+///
+/// With `align = N`, the static's type is wrapped in
+/// `hpm_riscv_rt::align::Aligned`, which derefs to the declared type.
+///
+/// # Example
+///
+/// ```ignore
+/// use hpm_riscv_rt::noncacheable;
+///
+/// #[noncacheable]
+/// static mut RX_BUFFER: [u8; 1024] = [0; 1024];
+///
+/// #[noncacheable(align = 64)]
+/// static mut DESCRIPTORS: [Descriptor; 8] = [Descriptor::new(); 8];
+/// ```
+#[proc_macro_attribute]
+pub fn noncacheable(args: TokenStream, input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as PlacementArgs);
+    let item = parse_macro_input!(input as Item);
+
+    let Item::Static(mut item) = item else {
+        return syn::Error::new(
+            item.span(),
+            "#[noncacheable] can only be applied to statics",
+        )
+        .to_compile_error()
+        .into();
+    };
+
+    let section = match args.static_kind(&item, "#[noncacheable]") {
+        Ok(SectionKind::Bss) => ".noncacheable.bss",
+        Ok(_) => ".noncacheable.data",
+        Err(e) => return e.to_compile_error().into(),
+    };
+    if let Some(align) = args.align {
+        align_static(&mut item, align);
+    }
+
+    quote!(
+        #[unsafe(link_section = #section)]
+        #item
+    )
+    .into()
+}
+
+/// Place a static into AHB SRAM (`AHB_SRAM`).
+///
+/// Statics are placed into `.ahb_sram.data` or `.ahb_sram.bss`, using the
+/// same classification and `bss`/`data` arguments as `#[fast]`, and registered with the startup
+/// copy/zero tables. Plain `#[link_section = ".ahb_sram"]` statics are
+/// still left untouched at boot.
+///
+/// With `align = N`, the static's type is wrapped in
+/// `hpm_riscv_rt::align::Aligned`, which derefs to the declared type.
+///
+/// # Example
+///
+/// ```ignore
+/// use hpm_riscv_rt::ahb_sram;
+///
+/// #[ahb_sram(align = 32)]
+/// static mut DMA_BUFFER: [u8; 512] = [0; 512];
+/// ```
+#[proc_macro_attribute]
+pub fn ahb_sram(args: TokenStream, input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as PlacementArgs);
+    let item = parse_macro_input!(input as Item);
+
+    let Item::Static(mut item) = item else {
+        return syn::Error::new(item.span(), "#[ahb_sram] can only be applied to statics")
+            .to_compile_error()
+            .into();
+    };
+
+    let bss = match args.static_kind(&item, "#[ahb_sram]") {
+        Ok(kind) => kind == SectionKind::Bss,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let section = if bss {
+        ".ahb_sram.bss"
+    } else {
+        ".ahb_sram.data"
+    };
+    if let Some(align) = args.align {
+        align_static(&mut item, align);
+    }
+    let entry = init_table_entry(&item, bss);
+
+    quote!(
+        #[unsafe(link_section = #section)]
+        #item
+
+        #entry
+    )
+    .into()
+}
+
 /// Argument for the external_interrupt attribute.
 struct ExternalInterruptArg {
     interrupt: syn::Path,
@@ -203,3 +1123,89 @@ pub fn external_interrupt(args: TokenStream, input: TokenStream) -> TokenStream
     )
     .into()
 }
+
+/// Register a function as the U-mode syscall handler for `ecall` number `N`
+/// (requires the `user-mode` feature of hpm-riscv-rt).
+///
+/// Up to six arguments are taken from `a0..a5` of the trap frame and cast
+/// with `as`, so they must be integers or raw pointers. The return value is
+/// cast to `usize` and written to `a0` (0 for `()`). The handler runs in
+/// M-mode with interrupts disabled.
+///
+/// Registering the same number twice is a link error.
+///
+/// # Example
+///
+/// ```ignore
+/// use hpm_riscv_rt::syscall;
+///
+/// #[syscall(2)]
+/// fn uart_write(buf: *const u8, len: usize) -> isize {
+///     // Validate `buf..buf + len` is application memory, then write
+///     len as isize
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn syscall(args: TokenStream, input: TokenStream) -> TokenStream {
+    let id = parse_macro_input!(args as syn::LitInt);
+    let f = parse_macro_input!(input as ItemFn);
+
+    let id_value = match id.base10_parse::<u32>() {
+        Ok(v) => v,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    if let Err(e) = reject_generic_fn(&f.sig, "#[syscall]") {
+        return e.to_compile_error().into();
+    }
+    if f.sig.inputs.len() > 6 {
+        return syn::Error::new(
+            f.sig.inputs.span(),
+            "#[syscall] handlers take at most 6 arguments",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    let mut args = Vec::new();
+    for (i, input) in f.sig.inputs.iter().enumerate() {
+        let syn::FnArg::Typed(arg) = input else {
+            return syn::Error::new(input.span(), "#[syscall] cannot be applied to methods")
+                .to_compile_error()
+                .into();
+        };
+        let ty = &arg.ty;
+        let reg = quote::format_ident!("a{}", i);
+        args.push(quote!(frame.#reg as #ty));
+    }
+
+    let fn_name = &f.sig.ident;
+    let call = if f.sig.unsafety.is_some() {
+        quote!(unsafe { #fn_name(#(#args),*) })
+    } else {
+        quote!(#fn_name(#(#args),*))
+    };
+    let store = match &f.sig.output {
+        syn::ReturnType::Default => quote!(#call; frame.a0 = 0;),
+        syn::ReturnType::Type(..) => quote!(frame.a0 = #call as usize;),
+    };
+    let export_name = format!("__hpm_syscall_{id_value}");
+
+    quote!(
+        #f
+
+        const _: () = {
+            unsafe extern "C" fn trampoline(frame: &mut ::hpm_riscv_rt::TrapFrame) {
+                #store
+            }
+
+            #[used]
+            #[unsafe(link_section = ".syscall_table")]
+            #[unsafe(export_name = #export_name)]
+            static ENTRY: ::hpm_riscv_rt::user::SyscallEntry = ::hpm_riscv_rt::user::SyscallEntry {
+                id: #id_value as usize,
+                handler: trampoline,
+            };
+        };
+    )
+    .into()
+}
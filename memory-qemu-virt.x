@@ -0,0 +1,31 @@
+/* QEMU `virt` machine memory layout (with the `emulator` feature)
+ *
+ * Use in place of the board's memory.x:
+ *   -Tmemory-qemu-virt.x
+ *
+ * virt has no flash, ILM or DLM: everything lives in its RAM at 0x80000000
+ * (128M by default), split to mimic an HPMicro part so that the copy loops
+ * of `_hpm_start` still run. QEMU loads the ELF at its load addresses and
+ * starts at the entry point with `-bios none`.
+ */
+
+MEMORY
+{
+    FLASH       : ORIGIN = 0x80000000, LENGTH = 4M
+    ILM         : ORIGIN = 0x80400000, LENGTH = 256K
+    DLM         : ORIGIN = 0x80440000, LENGTH = 256K
+    AHB_SRAM    : ORIGIN = 0x80480000, LENGTH = 32K
+    RAM         : ORIGIN = 0x80500000, LENGTH = 11M
+}
+
+REGION_ALIAS("REGION_TEXT", FLASH);
+REGION_ALIAS("REGION_RODATA", FLASH);
+REGION_ALIAS("REGION_DATA", RAM);
+REGION_ALIAS("REGION_BSS", RAM);
+REGION_ALIAS("REGION_HEAP", RAM);
+REGION_ALIAS("REGION_STACK", RAM);
+
+REGION_ALIAS("REGION_VECTOR", ILM);
+REGION_ALIAS("REGION_FASTTEXT", ILM);
+REGION_ALIAS("REGION_FASTDATA", DLM);
+REGION_ALIAS("REGION_NONCACHEABLE_RAM", DLM);
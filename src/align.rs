@@ -0,0 +1,73 @@
+//! Over-aligned wrapper for statics.
+//!
+//! Rust has no stable way to raise the alignment of a single static, so
+//! `#[noncacheable(align = N)]` and `#[ahb_sram(align = N)]` wrap the static's
+//! type in [`Aligned`]. The wrapper derefs to the original type.
+//!
+//! ```ignore
+//! use hpm_riscv_rt::align::{Aligned, A64};
+//!
+//! static mut DESC: Aligned<A64, [u32; 16]> = Aligned::new([0; 16]);
+//! ```
+
+use core::ops::{Deref, DerefMut};
+
+/// `T` aligned to at least the alignment of the marker type `A`.
+#[repr(C)]
+pub struct Aligned<A, T: ?Sized> {
+    _alignment: [A; 0],
+    value: T,
+}
+
+impl<A, T> Aligned<A, T> {
+    /// Wrap a value.
+    pub const fn new(value: T) -> Self {
+        Self {
+            _alignment: [],
+            value,
+        }
+    }
+
+    /// Unwrap the value.
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<A, T: ?Sized> Deref for Aligned<A, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<A, T: ?Sized> DerefMut for Aligned<A, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+macro_rules! alignment_markers {
+    ($($name:ident = $align:literal),* $(,)?) => {
+        $(
+            #[doc = concat!("Alignment marker: ", stringify!($align), " bytes.")]
+            #[repr(align($align))]
+            pub struct $name;
+        )*
+    };
+}
+
+alignment_markers! {
+    A4 = 4,
+    A8 = 8,
+    A16 = 16,
+    A32 = 32,
+    A64 = 64,
+    A128 = 128,
+    A256 = 256,
+    A512 = 512,
+    A1024 = 1024,
+    A2048 = 2048,
+    A4096 = 4096,
+}
@@ -1,17 +1,45 @@
 //! Assembly entry point and startup code for HPMicro RISC-V MCUs.
 //!
 //! This module provides the `_start` entry point that:
-//! 1. Initializes global pointer and stack pointer
-//! 2. Calls `__pre_init` hook
-//! 3. Initializes .data and .bss sections
-//! 4. Calls `_setup_interrupts`
-//! 5. Jumps to `main`
+//! 1. Initializes global pointer and stack pointer, paints the stack
+//!    (`stack-paint` feature)
+//! 2. Calls `__pre_init` hook and the `pre_ram` stage of `#[init]` functions
+//! 3. Walks the linker-generated copy and zero tables
+//!    (.data, .bss, .fast, .fast.data, .fast.bss, .noncacheable.* and user sections)
+//! 4. Jumps to `_hpm_start_rust`
 
 use core::arch::global_asm;
 
+// Stack painting (stack-paint feature): fill `_estack.._sstack` with
+// `stack::PAINT` before anything uses the stack.
+#[cfg(feature = "stack-paint")]
+macro_rules! paint_stack {
+    () => {
+        r#"
+    /* Paint the stack for stack::high_water_mark() */
+    la t0, _estack
+    la t1, _sstack
+    li t2, 0xCDCDCDCD
+1:
+    bgeu t0, t1, 2f
+    sw t2, 0(t0)
+    addi t0, t0, 4
+    j 1b
+2:
+"#
+    };
+}
+
+#[cfg(not(feature = "stack-paint"))]
+macro_rules! paint_stack {
+    () => {
+        ""
+    };
+}
+
 // Entry point of all programs (_start)
 // Initializes stack pointer, global pointer, then calls _start_rust
-global_asm!(
+global_asm!(concat!(
     r#"
     .section .init, "ax"
     .global _hpm_start
@@ -26,6 +54,9 @@ _hpm_start:
 
     /* Initialize stack pointer */
     la sp, _sstack
+"#,
+    paint_stack!(),
+    r#"
 
     /* Set pre-init trap handler (simple infinite loop) */
     la t0, _pre_init_trap
@@ -40,64 +71,26 @@ _hpm_start:
     /* Call pre-init hook (before RAM is initialized) */
     call __pre_init
 
-    /* Initialize .data section */
-    la a0, _sdata
-    la a1, _edata
-    la a2, _sidata
-    bgeu a0, a1, 2f
+    /* Run #[init(stage = pre_ram)] functions in priority order */
+    la s0, __hpm_init_pre_ram_start__
+    la s1, __hpm_init_pre_ram_end__
 1:
-    lw t0, 0(a2)
-    sw t0, 0(a0)
-    addi a0, a0, 4
-    addi a2, a2, 4
-    bltu a0, a1, 1b
+    bgeu s0, s1, 2f
+    lw t0, 0(s0)
+    addi s0, s0, 4
+    jalr t0
+    j 1b
 2:
 
-    /* Initialize .bss section */
-    la a0, _sbss
-    la a1, _ebss
-    bgeu a0, a1, 4f
-3:
-    sw zero, 0(a0)
-    addi a0, a0, 4
-    bltu a0, a1, 3b
-4:
+    /* Initialize .data, .fast, .fast.data, .noncacheable.data and user sections */
+    la a0, __copy_table_start__
+    la a1, __copy_table_end__
+    call _hpm_run_copy_table
 
-    /* Initialize .fast section (ILM) */
-    la a0, _sfast
-    la a1, _efast
-    la a2, _sifast
-    bgeu a0, a1, 6f
-5:
-    lw t0, 0(a2)
-    sw t0, 0(a0)
-    addi a0, a0, 4
-    addi a2, a2, 4
-    bltu a0, a1, 5b
-6:
-
-    /* Initialize .fast.data section (DLM) */
-    la a0, __fast_data_start__
-    la a1, __fast_data_end__
-    la a2, __fast_data_load_addr__
-    bgeu a0, a1, 8f
-7:
-    lw t0, 0(a2)
-    sw t0, 0(a0)
-    addi a0, a0, 4
-    addi a2, a2, 4
-    bltu a0, a1, 7b
-8:
-
-    /* Initialize .fast.bss section */
-    la a0, __fast_bss_start__
-    la a1, __fast_bss_end__
-    bgeu a0, a1, 10f
-9:
-    sw zero, 0(a0)
-    addi a0, a0, 4
-    bltu a0, a1, 9b
-10:
+    /* Zero .bss, .fast.bss, .noncacheable.bss and user sections */
+    la a0, __zero_table_start__
+    la a1, __zero_table_end__
+    call _hpm_run_zero_table
 
     /* Call Rust startup code */
     call _hpm_start_rust
@@ -107,6 +100,90 @@ _hpm_start:
 
     .size _hpm_start, . - _hpm_start
 "#
+));
+
+// Copy table walker.
+// a0 = table start, a1 = table end. Each entry is (src, dst, len in bytes).
+// Copies words when both pointers are word aligned, bytes otherwise.
+// Only clobbers a0 and t0-t5, so it is safe to call before RAM is initialized.
+global_asm!(
+    r#"
+    .section .init, "ax"
+    .global _hpm_run_copy_table
+    .type _hpm_run_copy_table, @function
+
+_hpm_run_copy_table:
+    bgeu a0, a1, 5f
+1:
+    lw t0, 0(a0)
+    lw t1, 4(a0)
+    lw t2, 8(a0)
+    addi a0, a0, 12
+    add t2, t1, t2
+    or t3, t0, t1
+    andi t3, t3, 3
+    bnez t3, 3f
+2:
+    sub t4, t2, t1
+    li t5, 4
+    bltu t4, t5, 3f
+    lw t3, 0(t0)
+    sw t3, 0(t1)
+    addi t0, t0, 4
+    addi t1, t1, 4
+    j 2b
+3:
+    bgeu t1, t2, 4f
+    lbu t3, 0(t0)
+    sb t3, 0(t1)
+    addi t0, t0, 1
+    addi t1, t1, 1
+    j 3b
+4:
+    bltu a0, a1, 1b
+5:
+    ret
+
+    .size _hpm_run_copy_table, . - _hpm_run_copy_table
+"#
+);
+
+// Zero table walker.
+// a0 = table start, a1 = table end. Each entry is (dst, len in bytes).
+global_asm!(
+    r#"
+    .section .init, "ax"
+    .global _hpm_run_zero_table
+    .type _hpm_run_zero_table, @function
+
+_hpm_run_zero_table:
+    bgeu a0, a1, 5f
+1:
+    lw t1, 0(a0)
+    lw t2, 4(a0)
+    addi a0, a0, 8
+    add t2, t1, t2
+    andi t3, t1, 3
+    bnez t3, 3f
+2:
+    sub t4, t2, t1
+    li t5, 4
+    bltu t4, t5, 3f
+    sw zero, 0(t1)
+    addi t1, t1, 4
+    j 2b
+3:
+    bgeu t1, t2, 4f
+    sb zero, 0(t1)
+    addi t1, t1, 1
+    j 3b
+4:
+    bltu a0, a1, 1b
+5:
+    ret
+
+    .size _hpm_run_zero_table, . - _hpm_run_zero_table
+"#
 );
 
 // Pre-init trap handler - simple infinite loop
@@ -139,6 +216,48 @@ default_pre_init:
 "#
 );
 
+// Default sdram_init function (does nothing)
+global_asm!(
+    r#"
+    .section .init, "ax"
+    .weak default_sdram_init
+    .type default_sdram_init, @function
+
+default_sdram_init:
+    ret
+
+    .size default_sdram_init, . - default_sdram_init
+"#
+);
+
+// Default startup phase hook (does nothing, shared by all phases)
+global_asm!(
+    r#"
+    .section .init, "ax"
+    .weak default_startup_hook
+    .type default_startup_hook, @function
+
+default_startup_hook:
+    ret
+
+    .size default_startup_hook, . - default_startup_hook
+"#
+);
+
+// Default OOM hook (does nothing, the allocation error follows)
+global_asm!(
+    r#"
+    .section .init, "ax"
+    .weak default_oom
+    .type default_oom, @function
+
+default_oom:
+    ret
+
+    .size default_oom, . - default_oom
+"#
+);
+
 // Default mp_hook (single-hart: always returns true)
 global_asm!(
     r#"
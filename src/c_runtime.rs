@@ -0,0 +1,233 @@
+//! Newlib and picolibc syscall shims for linked C code (`c-runtime`
+//! feature).
+//!
+//! C drivers that call `printf`, `malloc` or `assert` need the low-level
+//! syscalls of their libc. This module provides them:
+//!
+//! - `_sbrk`: grows the C heap over `_sc_heap.._ec_heap` (set `_c_heap_size`
+//!   in memory.x). Without the `alloc` feature, the `_heap_size` bytes before
+//!   it are free as well, so the C heap starts at `_sheap`.
+//! - `_write`: stdout and stderr go to the sink registered with
+//!   [`set_sink`], or are discarded
+//! - `_read`: stdin is always at end of file
+//! - `_fstat`, `_isatty`: stdin/stdout/stderr are terminals, so stdout is
+//!   line buffered
+//! - `_close`, `_lseek`, `_open`, `_getpid`, `_kill`: fail or do nothing
+//! - `_exit`: panics, so `abort()` and failed `assert`s end up in the panic
+//!   handler
+//!
+//! ```ignore
+//! use hpm_riscv_rt::c_runtime;
+//!
+//! c_runtime::set_sink(|bytes| {
+//!     let _ = rtt_channel().write(bytes);
+//! });
+//! ```
+//!
+//! Errors are reported through `__errno()`, which newlib resolves to the
+//! current `struct _reent`, so errno stays per context when the application
+//! switches `_impure_ptr`.
+//!
+//! picolibc calls `read`, `write`, `fstat` and `sbrk` without the leading
+//! underscore: the `picolibc` feature also exports those names and sets the
+//! global `errno` instead. The runtime does not set up thread-local storage,
+//! so picolibc must be built without it (`-Dthread-local-storage=false`).
+
+use core::ffi::{c_char, c_int, c_void};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+const STDIN: c_int = 0;
+const STDERR: c_int = 2;
+
+// newlib errno values
+const EBADF: c_int = 9;
+const ENOMEM: c_int = 12;
+const ESPIPE: c_int = 29;
+const ENOSYS: c_int = 88;
+
+/// `S_IFCHR`, character device.
+const S_IFCHR: u32 = 0o020000;
+
+/// Offset of `st_mode` in `struct stat`, after the 16-bit `st_dev` and
+/// `st_ino` of newlib on RV32.
+const ST_MODE_OFFSET: usize = 4;
+
+/// Current program break, 0 until the first `_sbrk`.
+static BRK: AtomicUsize = AtomicUsize::new(0);
+
+/// Output sink for stdout and stderr, 0 if none.
+static SINK: AtomicUsize = AtomicUsize::new(0);
+
+/// Send stdout and stderr of C code to `sink`.
+///
+/// The sink may be called from any context that calls into C code,
+/// including interrupt handlers.
+pub fn set_sink(sink: fn(&[u8])) {
+    SINK.store(sink as *const () as usize, Ordering::Release);
+}
+
+#[cfg(not(feature = "picolibc"))]
+fn set_errno(value: c_int) {
+    extern "C" {
+        fn __errno() -> *mut c_int;
+    }
+
+    // SAFETY: the libc returns the errno of the current context.
+    unsafe { *__errno() = value };
+}
+
+#[cfg(feature = "picolibc")]
+fn set_errno(value: c_int) {
+    extern "C" {
+        static mut errno: c_int;
+    }
+
+    // SAFETY: without thread-local storage, picolibc's errno is a plain global.
+    unsafe { *core::ptr::addr_of_mut!(errno) = value };
+}
+
+fn is_std(fd: c_int) -> bool {
+    (STDIN..=STDERR).contains(&fd)
+}
+
+#[no_mangle]
+extern "C" fn _sbrk(increment: isize) -> *mut c_void {
+    extern "C" {
+        #[cfg(not(feature = "alloc"))]
+        static _sheap: u8;
+        #[cfg(feature = "alloc")]
+        static _sc_heap: u8;
+        static _ec_heap: u8;
+    }
+
+    // `_sheap.._eheap` belongs to the global allocator with `alloc`
+    #[cfg(not(feature = "alloc"))]
+    let start = core::ptr::addr_of!(_sheap) as usize;
+    #[cfg(feature = "alloc")]
+    let start = core::ptr::addr_of!(_sc_heap) as usize;
+    let end = core::ptr::addr_of!(_ec_heap) as usize;
+    let mut brk = BRK.load(Ordering::Relaxed);
+    loop {
+        let current = if brk == 0 { start } else { brk };
+        let new = current.wrapping_add_signed(increment);
+        if new < start || new > end {
+            set_errno(ENOMEM);
+            return usize::MAX as *mut c_void;
+        }
+        match BRK.compare_exchange_weak(brk, new, Ordering::Relaxed, Ordering::Relaxed) {
+            Ok(_) => return current as *mut c_void,
+            Err(actual) => brk = actual,
+        }
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn _write(fd: c_int, buf: *const c_void, len: usize) -> isize {
+    if !is_std(fd) || fd == STDIN {
+        set_errno(EBADF);
+        return -1;
+    }
+    // `buf` may be NULL for an empty write
+    if len == 0 {
+        return 0;
+    }
+    let sink = SINK.load(Ordering::Acquire);
+    if sink != 0 {
+        // SAFETY: only `set_sink` stores into SINK, always a `fn(&[u8])`.
+        let sink: fn(&[u8]) = core::mem::transmute(sink);
+        sink(core::slice::from_raw_parts(buf as *const u8, len));
+    }
+    len as isize
+}
+
+#[no_mangle]
+extern "C" fn _read(fd: c_int, _buf: *mut c_void, _len: usize) -> isize {
+    if fd != STDIN {
+        set_errno(EBADF);
+        return -1;
+    }
+    0
+}
+
+#[no_mangle]
+unsafe extern "C" fn _fstat(fd: c_int, st: *mut c_void) -> c_int {
+    if !is_std(fd) {
+        set_errno(EBADF);
+        return -1;
+    }
+    (st as *mut u8)
+        .add(ST_MODE_OFFSET)
+        .cast::<u32>()
+        .write(S_IFCHR);
+    0
+}
+
+#[no_mangle]
+extern "C" fn _isatty(fd: c_int) -> c_int {
+    if !is_std(fd) {
+        set_errno(EBADF);
+        return 0;
+    }
+    1
+}
+
+#[no_mangle]
+extern "C" fn _close(fd: c_int) -> c_int {
+    if !is_std(fd) {
+        set_errno(EBADF);
+        return -1;
+    }
+    0
+}
+
+#[no_mangle]
+extern "C" fn _lseek(fd: c_int, _offset: isize, _whence: c_int) -> isize {
+    set_errno(if is_std(fd) { ESPIPE } else { EBADF });
+    -1
+}
+
+#[no_mangle]
+extern "C" fn _open(_path: *const c_char, _flags: c_int, _mode: c_int) -> c_int {
+    set_errno(ENOSYS);
+    -1
+}
+
+#[no_mangle]
+extern "C" fn _getpid() -> c_int {
+    1
+}
+
+#[no_mangle]
+extern "C" fn _kill(_pid: c_int, _sig: c_int) -> c_int {
+    set_errno(ENOSYS);
+    -1
+}
+
+#[cfg(feature = "picolibc")]
+#[no_mangle]
+extern "C" fn sbrk(increment: isize) -> *mut c_void {
+    _sbrk(increment)
+}
+
+#[cfg(feature = "picolibc")]
+#[no_mangle]
+unsafe extern "C" fn write(fd: c_int, buf: *const c_void, len: usize) -> isize {
+    _write(fd, buf, len)
+}
+
+#[cfg(feature = "picolibc")]
+#[no_mangle]
+extern "C" fn read(fd: c_int, buf: *mut c_void, len: usize) -> isize {
+    _read(fd, buf, len)
+}
+
+#[cfg(feature = "picolibc")]
+#[no_mangle]
+unsafe extern "C" fn fstat(fd: c_int, st: *mut c_void) -> c_int {
+    _fstat(fd, st)
+}
+
+#[no_mangle]
+extern "C" fn _exit(status: c_int) -> ! {
+    panic!("C code exited with status {}", status);
+}
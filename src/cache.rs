@@ -0,0 +1,237 @@
+//! L1 cache maintenance for DMA buffers and loaded code.
+//!
+//! `_hpm_start_rust` enables both L1 caches, unless a
+//! `#[startup_hook(before_cache_enable)]` turns them off in [`Caches`]. DMA into or out of cacheable
+//! memory (AXI SRAM, SDRAM) then needs explicit maintenance:
+//!
+//! - before the device reads a buffer: [`dcache_writeback`]
+//! - after the device wrote a buffer: [`dcache_invalidate`]
+//! - after writing code to RAM: [`icache_sync`]
+//!
+//! Writeback and flush round the range out to whole cache lines, which only
+//! writes back extra data. Invalidate would discard whatever shares the first
+//! and last line, so it requires a line-aligned range. [`CacheAligned`] (alias
+//! [`DmaBuffer`]) pads a value to whole lines, so it can always be
+//! invalidated on its own:
+//!
+//! ```ignore
+//! use hpm_riscv_rt::cache::DmaBuffer;
+//!
+//! #[hpm_riscv_rt::sdram]
+//! static mut RX: DmaBuffer<[u8; 1500]> = DmaBuffer::new([0; 1500]);
+//!
+//! let rx = unsafe { &mut *(&raw mut RX) };
+//! rx.flush(); // no dirty lines may be evicted over the incoming data
+//! start_dma_rx(rx.as_ptr());
+//! wait_dma();
+//! unsafe { rx.invalidate() }; // drop lines loaded during the transfer
+//! ```
+//!
+//! Memory made non-cacheable through PMA (e.g. `#[noncacheable]` statics)
+//! needs none of this.
+
+use core::ops::{Deref, DerefMut};
+
+#[cfg(not(feature = "emulator"))]
+use andes_riscv::l1c;
+use andes_riscv::l1c::cctl_cmds;
+
+/// L1 cache line size of HPMicro cores, in bytes.
+pub const LINE_SIZE: usize = 64;
+
+/// Write dirty D-cache lines covering `addr..addr + len` back to memory.
+///
+/// The range is rounded out to whole cache lines.
+pub fn dcache_writeback(addr: usize, len: usize) {
+    if let Some((start, size)) = line_range(addr, len) {
+        // SAFETY: writeback does not change memory contents seen by the CPU.
+        unsafe { range_op(cctl_cmds::L1D_VA_WB, start, size) };
+    }
+}
+
+/// Discard D-cache lines covering `addr..addr + len`, so the next reads
+/// fetch from memory.
+///
+/// # Safety
+///
+/// `addr` and `len` must be multiples of [`LINE_SIZE`]: unwritten changes in
+/// the range are lost. Panics if they are not.
+pub unsafe fn dcache_invalidate(addr: usize, len: usize) {
+    assert!(
+        (addr | len) & (LINE_SIZE - 1) == 0,
+        "invalidated range must be aligned to cache lines"
+    );
+    if len != 0 {
+        range_op(cctl_cmds::L1D_VA_INVAL, addr, len);
+    }
+}
+
+/// Write back and then discard D-cache lines covering `addr..addr + len`.
+///
+/// The range is rounded out to whole cache lines.
+pub fn dcache_flush(addr: usize, len: usize) {
+    if let Some((start, size)) = line_range(addr, len) {
+        // SAFETY: dirty data is written back before the lines are dropped.
+        unsafe { range_op(cctl_cmds::L1D_VA_WBINVAL, start, size) };
+    }
+}
+
+/// Discard I-cache lines covering `addr..addr + len`.
+///
+/// Code written through the D-cache must be written back first, see
+/// [`icache_sync`].
+pub fn icache_invalidate(addr: usize, len: usize) {
+    if let Some((start, size)) = line_range(addr, len) {
+        // SAFETY: I-cache lines are never dirty.
+        unsafe {
+            range_op(cctl_cmds::L1I_VA_INVAL, start, size);
+            core::arch::asm!("fence.i");
+        }
+    }
+}
+
+/// Make code written to `addr..addr + len` visible to instruction fetch:
+/// D-cache writeback, then I-cache invalidate.
+pub fn icache_sync(addr: usize, len: usize) {
+    dcache_writeback(addr, len);
+    icache_invalidate(addr, len);
+}
+
+/// Write back the whole D-cache.
+pub fn dcache_writeback_all() {
+    // SAFETY: writeback does not change memory contents seen by the CPU.
+    unsafe {
+        core::arch::asm!("fence iorw, iorw");
+        #[cfg(not(feature = "emulator"))]
+        l1c::dc_writeback_all();
+    }
+}
+
+/// Write back and invalidate the whole D-cache, e.g. before changing PMA
+/// attributes or handing memory to another bus master.
+pub fn dcache_flush_all() {
+    // SAFETY: dirty data is written back before the lines are dropped.
+    unsafe {
+        core::arch::asm!("fence iorw, iorw");
+        #[cfg(not(feature = "emulator"))]
+        l1c::dc_flush_all();
+    }
+}
+
+/// L1 caches enabled at startup.
+///
+/// Passed to the `#[startup_hook(before_cache_enable)]` hook with both set,
+/// e.g. to keep the D-cache off while debugging DMA:
+///
+/// ```ignore
+/// #[hpm_riscv_rt::startup_hook(before_cache_enable)]
+/// fn caches(caches: &mut hpm_riscv_rt::cache::Caches) {
+///     caches.dcache = false;
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Caches {
+    /// Enable the I-cache
+    pub icache: bool,
+    /// Enable the D-cache
+    pub dcache: bool,
+}
+
+/// Round `addr..addr + len` out to whole lines, `None` if empty.
+fn line_range(addr: usize, len: usize) -> Option<(usize, usize)> {
+    if len == 0 {
+        return None;
+    }
+    let start = addr & !(LINE_SIZE - 1);
+    let end = (addr + len).next_multiple_of(LINE_SIZE);
+    Some((start, end - start))
+}
+
+/// Run a CCTL operation on a line-aligned range, after prior stores.
+/// Only the fence with the `emulator` feature.
+unsafe fn range_op(opcode: u8, addr: usize, len: usize) {
+    core::arch::asm!("fence iorw, iorw");
+    #[cfg(not(feature = "emulator"))]
+    l1c::l1c_op(opcode, addr as u32, len as u32);
+    #[cfg(feature = "emulator")]
+    let _ = (opcode, addr, len);
+}
+
+/// `T` aligned to and padded to whole cache lines.
+///
+/// Maintenance on a `CacheAligned` value never touches neighbouring data.
+#[repr(C, align(64))]
+pub struct CacheAligned<T: ?Sized>(T);
+
+/// A buffer shared with DMA, see [`CacheAligned`].
+pub type DmaBuffer<T> = CacheAligned<T>;
+
+// LINE_SIZE is duplicated in `repr(align)`, keep them in sync
+const _: () = assert!(core::mem::align_of::<CacheAligned<u8>>() == LINE_SIZE);
+
+impl<T> CacheAligned<T> {
+    /// Wrap a value.
+    pub const fn new(value: T) -> Self {
+        Self(value)
+    }
+
+    /// Unwrap the value.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: ?Sized> CacheAligned<T> {
+    /// Address of the value.
+    pub fn as_ptr(&self) -> *const T {
+        &self.0
+    }
+
+    /// Mutable address of the value.
+    pub fn as_mut_ptr(&mut self) -> *mut T {
+        &mut self.0
+    }
+
+    /// Write the value back to memory, before a device reads it.
+    pub fn writeback(&self) {
+        dcache_writeback(self.addr(), self.padded_len());
+    }
+
+    /// Discard cached copies of the value, after a device wrote it.
+    ///
+    /// # Safety
+    ///
+    /// Unwritten CPU changes are lost, and memory must hold a valid `T`
+    /// (e.g. plain data written by the device).
+    pub unsafe fn invalidate(&mut self) {
+        // The value covers whole lines, neighbours are not affected
+        dcache_invalidate(self.addr(), self.padded_len());
+    }
+
+    /// Write back and discard cached copies of the value.
+    pub fn flush(&mut self) {
+        dcache_flush(self.addr(), self.padded_len());
+    }
+
+    fn addr(&self) -> usize {
+        self as *const Self as *const u8 as usize
+    }
+
+    fn padded_len(&self) -> usize {
+        core::mem::size_of_val(self)
+    }
+}
+
+impl<T: ?Sized> Deref for CacheAligned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: ?Sized> DerefMut for CacheAligned<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
@@ -0,0 +1,154 @@
+//! DMA memory from the free part of the non-cacheable region.
+//!
+//! `#[noncacheable]` statics fill `.noncacheable.data` and `.noncacheable.bss`
+//! from `__noncacheable_start__`. The rest, up to `__noncacheable_end__`, is
+//! a pool for buffers and descriptors that are only sized at init time. Memory
+//! is handed out by a bump allocator and never freed, so allocations are
+//! `'static`:
+//!
+//! ```ignore
+//! use core::alloc::Layout;
+//! use hpm_riscv_rt::dma;
+//!
+//! // ENET descriptors, 64-byte aligned
+//! let desc: &'static mut [[u32; 8]] = dma::leak_slice_aligned(16, [0; 8], 64).unwrap();
+//!
+//! let buf = dma::alloc(Layout::from_size_align(1536, 64).unwrap()).unwrap();
+//! ```
+//!
+//! The pool is only non-cacheable with the `pma-noncacheable` feature (or an
+//! equivalent PMA setup). [`Bump`] can manage other regions, e.g. a
+//! non-cacheable part of SDRAM.
+
+use core::alloc::Layout;
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// A lock-free bump allocator over `start..end`. Memory is never freed.
+pub struct Bump {
+    next: AtomicUsize,
+    end: AtomicUsize,
+}
+
+impl Bump {
+    /// An allocator over `start..end`.
+    pub const fn new(start: usize, end: usize) -> Self {
+        Self {
+            next: AtomicUsize::new(start),
+            end: AtomicUsize::new(end),
+        }
+    }
+
+    /// Allocate memory for `layout`, uninitialized.
+    ///
+    /// Returns `None` if the rest of the region is too small.
+    pub fn alloc(&self, layout: Layout) -> Option<NonNull<u8>> {
+        let end = self.end.load(Ordering::Relaxed);
+        let mut next = self.next.load(Ordering::Relaxed);
+        loop {
+            let start = next.checked_next_multiple_of(layout.align())?;
+            let new_next = start.checked_add(layout.size())?;
+            if new_next > end {
+                return None;
+            }
+            match self.next.compare_exchange_weak(
+                next,
+                new_next,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return NonNull::new(start as *mut u8),
+                Err(current) => next = current,
+            }
+        }
+    }
+
+    /// Move `value` into the region and return a `'static` reference to it.
+    pub fn leak<T>(&self, value: T) -> Option<&'static mut T> {
+        let ptr = self.alloc(Layout::new::<T>())?.cast::<T>();
+        // SAFETY: freshly allocated, aligned and never handed out again.
+        unsafe {
+            ptr.as_ptr().write(value);
+            Some(&mut *ptr.as_ptr())
+        }
+    }
+
+    /// Allocate `len` copies of `value`, aligned to at least `align` bytes
+    /// (a power of two).
+    pub fn leak_slice_aligned<T: Copy>(
+        &self,
+        len: usize,
+        value: T,
+        align: usize,
+    ) -> Option<&'static mut [T]> {
+        let layout = Layout::array::<T>(len).ok()?.align_to(align).ok()?;
+        let ptr = self.alloc(layout)?.cast::<T>().as_ptr();
+        // SAFETY: freshly allocated for `len` elements, aligned and never
+        // handed out again.
+        unsafe {
+            for i in 0..len {
+                ptr.add(i).write(value);
+            }
+            Some(core::slice::from_raw_parts_mut(ptr, len))
+        }
+    }
+
+    /// Allocate `len` copies of `value`.
+    pub fn leak_slice<T: Copy>(&self, len: usize, value: T) -> Option<&'static mut [T]> {
+        self.leak_slice_aligned(len, value, core::mem::align_of::<T>())
+    }
+
+    /// Bytes left, ignoring alignment padding.
+    pub fn remaining(&self) -> usize {
+        self.end
+            .load(Ordering::Relaxed)
+            .saturating_sub(self.next.load(Ordering::Relaxed))
+    }
+}
+
+/// The pool, `__noncacheable_pool_start__..__noncacheable_pool_end__`.
+/// Bounds are filled in on first use, since symbol addresses are not `const`.
+static POOL: Bump = Bump::new(0, 0);
+
+fn pool() -> &'static Bump {
+    extern "C" {
+        static __noncacheable_pool_start__: u8;
+        static __noncacheable_pool_end__: u8;
+    }
+
+    if POOL.end.load(Ordering::Acquire) == 0 {
+        let start = core::ptr::addr_of!(__noncacheable_pool_start__) as usize;
+        let end = core::ptr::addr_of!(__noncacheable_pool_end__) as usize;
+        // Only the first caller sets `next`; `end` is the same for everyone
+        let _ = POOL
+            .next
+            .compare_exchange(0, start, Ordering::Relaxed, Ordering::Relaxed);
+        POOL.end.store(end, Ordering::Release);
+    }
+    &POOL
+}
+
+/// Allocate DMA memory for `layout`, uninitialized. See [`Bump::alloc`].
+pub fn alloc(layout: Layout) -> Option<NonNull<u8>> {
+    pool().alloc(layout)
+}
+
+/// Move `value` into DMA memory. See [`Bump::leak`].
+pub fn leak<T>(value: T) -> Option<&'static mut T> {
+    pool().leak(value)
+}
+
+/// Allocate `len` copies of `value` in DMA memory.
+pub fn leak_slice<T: Copy>(len: usize, value: T) -> Option<&'static mut [T]> {
+    pool().leak_slice(len, value)
+}
+
+/// Allocate `len` copies of `value` in DMA memory, aligned to `align`.
+pub fn leak_slice_aligned<T: Copy>(len: usize, value: T, align: usize) -> Option<&'static mut [T]> {
+    pool().leak_slice_aligned(len, value, align)
+}
+
+/// Bytes left in the pool.
+pub fn remaining() -> usize {
+    pool().remaining()
+}
@@ -0,0 +1,27 @@
+//! QEMU `virt` machine support (`emulator` feature).
+//!
+//! Lets firmware built on this crate boot on `qemu-system-riscv32 -M virt`,
+//! so startup, trap and interrupt code can be tested without hardware:
+//!
+//! ```text
+//! qemu-system-riscv32 -M virt -cpu rv32 -bios none -nographic \
+//!     -semihosting -kernel target/riscv32imafc-unknown-none-elf/debug/app
+//! ```
+//!
+//! Link with `-Tmemory-qemu-virt.x` (shipped with this crate) in place of the
+//! board's `memory.x`. With this feature:
+//!
+//! - L1 cache enable and the `cache` maintenance operations are skipped
+//!   (QEMU has no caches and no Andes CCTL CSRs)
+//! - PMA setup is skipped and [`pma::Builder::apply`](crate::pma::Builder::apply)
+//!   does nothing
+//! - The PLIC is the standard one at `0x0C00_0000` instead of `0xE400_0000`,
+//!   in direct mode: `mtvec` points at `CORE_LOCAL`, which claims external
+//!   interrupts, calls their handlers from the vector table and completes
+//!   them (see [`crate::trap`])
+//!
+//! Peripheral drivers and the machine timer (CLINT at `0x0200_0000` on virt)
+//! are up to the application.
+
+/// PLIC base address of the virt machine.
+pub const PLIC_BASE: usize = 0x0C00_0000;
@@ -0,0 +1,269 @@
+//! Heap allocator over `_sheap.._eheap`.
+//!
+//! With the `alloc` feature, [`Heap`] is installed as the
+//! `#[global_allocator]` and initialized in `_hpm_start_rust` before `main`.
+//! Set `_heap_size` in memory.x to reserve the heap, e.g.
+//! `_heap_size = 64K;`.
+//!
+//! The allocator is a first-fit free list that merges neighbouring free
+//! blocks. Each operation runs with interrupts masked, so interrupt handlers
+//! may allocate too. With `user-mode`, interrupts cannot be masked from
+//! U-mode, so M-mode interrupt handlers must not use the global heap.
+//!
+//! When an allocation fails, the `#[oom_handler]` hook is called with the
+//! layout before the allocation error is raised; see [`stats`] for usage.
+//!
+//! More heaps can be placed in other regions, e.g. SDRAM or AXI SRAM:
+//!
+//! ```ignore
+//! use core::alloc::Layout;
+//! use core::mem::MaybeUninit;
+//! use hpm_riscv_rt::heap::Heap;
+//!
+//! #[hpm_riscv_rt::sdram]
+//! static mut SDRAM_ARENA: MaybeUninit<[u8; 1 << 20]> = MaybeUninit::uninit();
+//! static SDRAM_HEAP: Heap = Heap::empty();
+//!
+//! unsafe { SDRAM_HEAP.init(&raw mut SDRAM_ARENA as usize, 1 << 20) };
+//! let frame = SDRAM_HEAP.alloc(Layout::from_size_align(320 * 240 * 2, 64).unwrap());
+//! ```
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::UnsafeCell;
+use core::ptr::{self, NonNull};
+
+/// Allocation granule: block sizes and addresses are multiples of this.
+const GRANULE: usize = 8;
+
+/// Free block header, stored in the free memory itself.
+#[repr(C)]
+struct Hole {
+    size: usize,
+    next: *mut Hole,
+}
+
+const _: () = assert!(core::mem::size_of::<Hole>() <= GRANULE);
+
+struct Inner {
+    head: *mut Hole,
+    size: usize,
+    used: usize,
+    peak: usize,
+}
+
+/// Heap usage, in bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Total heap size
+    pub size: usize,
+    /// Currently allocated, including rounding
+    pub used: usize,
+    /// Not allocated, possibly fragmented
+    pub free: usize,
+    /// Highest `used` so far
+    pub peak: usize,
+}
+
+/// A first-fit heap over a memory range given to [`init`](Self::init).
+pub struct Heap {
+    inner: UnsafeCell<Inner>,
+}
+
+// SAFETY: `inner` is only accessed with interrupts masked.
+unsafe impl Sync for Heap {}
+
+impl Heap {
+    /// A heap with no memory; every allocation fails until [`init`](Self::init).
+    pub const fn empty() -> Self {
+        Self {
+            inner: UnsafeCell::new(Inner {
+                head: ptr::null_mut(),
+                size: 0,
+                used: 0,
+                peak: 0,
+            }),
+        }
+    }
+
+    /// Give `start..start + size` to the heap. The range is trimmed to
+    /// 8-byte boundaries.
+    ///
+    /// # Safety
+    ///
+    /// The range must be valid, unused RAM for the rest of the program, and
+    /// the heap must not have been initialized before.
+    pub unsafe fn init(&self, start: usize, size: usize) {
+        let begin = start.next_multiple_of(GRANULE);
+        let end = (start + size) & !(GRANULE - 1);
+        self.lock(|inner| {
+            if end <= begin {
+                return;
+            }
+            let hole = begin as *mut Hole;
+            hole.write(Hole {
+                size: end - begin,
+                next: ptr::null_mut(),
+            });
+            inner.head = hole;
+            inner.size = end - begin;
+        });
+    }
+
+    /// Allocate memory for `layout`, or `None` if no free block fits.
+    pub fn alloc(&self, layout: Layout) -> Option<NonNull<u8>> {
+        let size = block_size(layout);
+        let align = layout.align().max(GRANULE);
+        self.lock(|inner| unsafe {
+            // Walk the free list by its links, so blocks can be unlinked
+            let mut link: *mut *mut Hole = &mut inner.head;
+            while !(*link).is_null() {
+                let hole = *link;
+                let start = hole as usize;
+                let end = start + (*hole).size;
+                let addr = start.next_multiple_of(align);
+                if addr + size <= end {
+                    let next = (*hole).next;
+                    // Keep the alignment gap in front as a free block
+                    let link = if addr == start {
+                        link
+                    } else {
+                        (*hole).size = addr - start;
+                        &mut (*hole).next
+                    };
+                    // And the rest behind the allocation
+                    *link = if addr + size < end {
+                        let rest = (addr + size) as *mut Hole;
+                        rest.write(Hole {
+                            size: end - (addr + size),
+                            next,
+                        });
+                        rest
+                    } else {
+                        next
+                    };
+                    inner.used += size;
+                    inner.peak = inner.peak.max(inner.used);
+                    return NonNull::new(addr as *mut u8);
+                }
+                link = &mut (*hole).next;
+            }
+            None
+        })
+    }
+
+    /// Return memory from [`alloc`](Self::alloc).
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must come from `alloc` on this heap with the same `layout`, and
+    /// must not be used afterwards.
+    pub unsafe fn dealloc(&self, ptr: NonNull<u8>, layout: Layout) {
+        let addr = ptr.as_ptr() as usize;
+        let mut size = block_size(layout);
+        self.lock(|inner| {
+            inner.used -= size;
+
+            // Find the neighbours, the list is sorted by address
+            let mut prev: *mut Hole = ptr::null_mut();
+            let mut link: *mut *mut Hole = &mut inner.head;
+            while !(*link).is_null() && (*link as usize) < addr {
+                prev = *link;
+                link = &mut (*prev).next;
+            }
+
+            let mut next = *link;
+            if !next.is_null() && addr + size == next as usize {
+                size += (*next).size;
+                next = (*next).next;
+            }
+            if !prev.is_null() && prev as usize + (*prev).size == addr {
+                (*prev).size += size;
+                (*prev).next = next;
+            } else {
+                let hole = addr as *mut Hole;
+                hole.write(Hole { size, next });
+                *link = hole;
+            }
+        });
+    }
+
+    /// Current usage.
+    pub fn stats(&self) -> Stats {
+        self.lock(|inner| Stats {
+            size: inner.size,
+            used: inner.used,
+            free: inner.size - inner.used,
+            peak: inner.peak,
+        })
+    }
+
+    fn lock<R>(&self, f: impl FnOnce(&mut Inner) -> R) -> R {
+        // SAFETY: interrupts are masked, nothing else accesses `inner`.
+        critical_section(|| f(unsafe { &mut *self.inner.get() }))
+    }
+}
+
+/// Rounded block size for `layout`.
+fn block_size(layout: Layout) -> usize {
+    layout.size().max(1).next_multiple_of(GRANULE)
+}
+
+#[cfg(not(any(feature = "user-mode", feature = "supervisor-mode")))]
+fn critical_section<R>(f: impl FnOnce() -> R) -> R {
+    riscv::interrupt::machine::free(f)
+}
+
+#[cfg(feature = "supervisor-mode")]
+fn critical_section<R>(f: impl FnOnce() -> R) -> R {
+    riscv::interrupt::supervisor::free(f)
+}
+
+// U-mode cannot mask interrupts, and U-mode code is not reentered by them
+#[cfg(feature = "user-mode")]
+fn critical_section<R>(f: impl FnOnce() -> R) -> R {
+    f()
+}
+
+unsafe impl GlobalAlloc for Heap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        match Heap::alloc(self, layout) {
+            Some(ptr) => ptr.as_ptr(),
+            None => {
+                if ptr::eq(self, &HEAP) {
+                    extern "Rust" {
+                        fn __hpm_oom(layout: Layout);
+                    }
+                    __hpm_oom(layout);
+                }
+                ptr::null_mut()
+            }
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if let Some(ptr) = NonNull::new(ptr) {
+            Heap::dealloc(self, ptr, layout);
+        }
+    }
+}
+
+/// The global heap.
+#[global_allocator]
+static HEAP: Heap = Heap::empty();
+
+/// Usage of the global heap.
+pub fn stats() -> Stats {
+    HEAP.stats()
+}
+
+/// Give `_sheap.._eheap` to the global heap. Called from `_hpm_start_rust`.
+pub(crate) unsafe fn init() {
+    extern "C" {
+        static _sheap: u8;
+        static _eheap: u8;
+    }
+
+    let start = core::ptr::addr_of!(_sheap) as usize;
+    let end = core::ptr::addr_of!(_eheap) as usize;
+    HEAP.init(start, end - start);
+}
@@ -45,20 +45,61 @@
 
 #![no_std]
 
+#[cfg(all(feature = "user-mode", feature = "supervisor-mode"))]
+compile_error!("features `user-mode` and `supervisor-mode` are mutually exclusive");
+
+// M-mode interrupts are always enabled while U-mode or S-mode code runs
+#[cfg(all(
+    feature = "keep-interrupts-disabled",
+    any(feature = "user-mode", feature = "supervisor-mode")
+))]
+compile_error!("feature `keep-interrupts-disabled` requires `main` to run in M-mode");
+
+pub mod align;
 mod asm;
+#[cfg(feature = "c-runtime")]
+pub mod c_runtime;
+pub mod cache;
+pub mod dma;
+#[cfg(feature = "emulator")]
+mod emulator;
+#[cfg(feature = "alloc")]
+pub mod heap;
+#[cfg(feature = "emulate-misaligned")]
+pub mod misaligned;
+pub mod noinit;
+pub mod pma;
+pub mod pmp;
+pub mod section;
+#[cfg(feature = "semihosting")]
+pub mod semihosting;
+pub mod stack;
+pub mod startup;
+#[cfg(feature = "supervisor-mode")]
+pub mod supervisor;
 pub mod trap;
-
-use andes_riscv::{
-    plic::{Plic, PlicExt},
-    register,
+#[cfg(feature = "user-mode")]
+pub mod user;
+
+use andes_riscv::plic::{Plic, PlicExt};
+use riscv::register::{
+    mcounteren, mie, mstatus,
+    mtvec::{self, Mtvec, TrapMode},
+    pmpaddr0, pmpcfg0, Permission, Range,
 };
-use riscv::register::{mcounteren, mie, mstatus, mtvec::{self, Mtvec, TrapMode}};
 
 // Re-export macros
-pub use hpm_riscv_rt_macros::{entry, pre_init, fast, external_interrupt};
+pub use hpm_riscv_rt_macros::{
+    ahb_sram, entry, external_interrupt, fast, init, init_section, noinit, noncacheable,
+    oom_handler, pre_init, sdram, sdram_init, startup_hook, syscall,
+};
 
-/// HPMicro PLIC base address (same for all series)
-const PLIC_BASE: usize = 0xE400_0000;
+/// HPMicro PLIC base address (same for all series), `0x0C00_0000` on QEMU
+/// with the `emulator` feature.
+#[cfg(not(feature = "emulator"))]
+pub const PLIC_BASE: usize = 0xE400_0000;
+#[cfg(feature = "emulator")]
+pub use emulator::PLIC_BASE;
 
 // ============ TrapFrame ============
 
@@ -107,232 +148,226 @@ pub struct TrapFrame {
 /// Rust startup function called from assembly after RAM is initialized.
 ///
 /// This function:
-/// 1. Enables FPU
-/// 2. Enables L1 Cache
-/// 3. Initializes non-cacheable sections
-/// 4. Sets up interrupts (PLIC vectored mode)
-/// 5. Calls `main`
+/// 1. Enables FPU, locks the null-pointer guard, calls `__sdram_init` and
+///    initializes `.sdram.*` sections
+/// 2. Enables L1 Cache, configures PMA and PMP (optional), initializes the
+///    heap (`alloc` feature)
+/// 3. Sets up interrupts (PLIC vectored mode)
+/// 4. Calls `main`, in U-mode or S-mode with the `user-mode` and
+///    `supervisor-mode` features
+///
+/// The `#[startup_hook]` phases and the `post_ram`/`pre_main` stages of
+/// [`startup`] run between these steps.
+///
+/// All RAM sections, including `.noncacheable.*`, are already initialized
+/// by `_hpm_start` from the linker-generated copy/zero tables.
+///
+/// # Safety
+///
+/// Must only be called once, from `_hpm_start`.
 #[no_mangle]
 pub unsafe extern "C" fn _hpm_start_rust() -> ! {
     extern "Rust" {
         fn main() -> !;
+        fn __hpm_after_ram_init();
+        fn __hpm_before_cache_enable(caches: &mut cache::Caches);
+        fn __hpm_after_cache_enable();
+        fn __hpm_before_interrupts();
     }
 
     extern "C" {
+        fn __sdram_init();
         fn _setup_interrupts();
     }
 
     // 1. Enable FPU (all HPMicro MCUs have FPU)
     mstatus::set_fs(mstatus::FS::Initial);
 
-    // 2. Enable L1 Cache
-    andes_riscv::l1c::ic_enable();
-    andes_riscv::l1c::dc_enable();
-    andes_riscv::l1c::dc_invalidate_all();
+    // 1.2. Make the null-pointer guard inaccessible (if configured)
+    configure_null_guard();
+
+    // 1.5. Bring up SDRAM and initialize .sdram.* sections.
+    // Done with caches disabled, so copied .sdram.text needs no cache maintenance.
+    __sdram_init();
+    section::init_sdram_sections();
+    __hpm_after_ram_init();
+    startup::run_post_ram();
+
+    // 2. Enable L1 Cache, unless the hook turns it off
+    let mut caches = cache::Caches {
+        icache: true,
+        dcache: true,
+    };
+    __hpm_before_cache_enable(&mut caches);
+    // QEMU has no caches and no Andes cache CSRs
+    #[cfg(feature = "emulator")]
+    let caches = cache::Caches {
+        icache: false,
+        dcache: false,
+    };
+    if caches.icache {
+        andes_riscv::l1c::ic_enable();
+    }
+    if caches.dcache {
+        andes_riscv::l1c::dc_enable();
+        andes_riscv::l1c::dc_invalidate_all();
+    }
+    __hpm_after_cache_enable();
 
     // 2.5. Configure PMA entries for non-cacheable regions
-    // HPM67xx needs both RTT fix and noncacheable region configured together
-    #[cfg(all(feature = "hpm67-fix", feature = "pma-noncacheable"))]
-    configure_pma_hpm67();
-
-    // Only RTT fix (no noncacheable region)
-    #[cfg(all(feature = "hpm67-fix", not(feature = "pma-noncacheable")))]
-    configure_rtt_noncacheable();
-
-    // Only noncacheable region (non-HPM67 chips)
-    #[cfg(all(feature = "pma-noncacheable", not(feature = "hpm67-fix")))]
-    configure_noncacheable_pma();
-
-    // 3. Initialize non-cacheable sections
-    init_noncacheable_sections();
-
-    // 4. Setup interrupts (PLIC vectored mode)
+    #[cfg(all(
+        any(feature = "hpm67-fix", feature = "pma-noncacheable"),
+        not(feature = "emulator")
+    ))]
+    configure_pma();
+
+    // 2.6. Protect text/rodata/data/stack with PMP entries from linker symbols
+    #[cfg(feature = "pmp")]
+    configure_pmp();
+
+    // 2.65. Give _sheap.._eheap to the global allocator
+    #[cfg(feature = "alloc")]
+    heap::init();
+
+    // 2.7. Traps are taken from M-mode until main is entered in U-mode or
+    // S-mode
+    #[cfg(any(feature = "user-mode", feature = "supervisor-mode"))]
+    riscv::register::mscratch::write(0);
+
+    // 3. Setup interrupts (PLIC vectored mode)
+    __hpm_before_interrupts();
     _setup_interrupts();
 
-    // 5. Jump to main
-    main()
+    // 3.5. Delegate S-mode traps and open PMP for S-mode
+    #[cfg(feature = "supervisor-mode")]
+    supervisor::configure();
+
+    // 4. Run C/C++ constructors and #[init(stage = pre_main)] functions,
+    // then jump to main
+    startup::run_init_array();
+    startup::run_pre_main();
+    #[cfg(not(any(feature = "user-mode", feature = "supervisor-mode")))]
+    main();
+    #[cfg(feature = "user-mode")]
+    user::enter(main, stack::app_stack_top());
+    #[cfg(feature = "supervisor-mode")]
+    supervisor::enter(main)
 }
 
-/// Initialize non-cacheable data and bss sections.
-#[inline(always)]
-unsafe fn init_noncacheable_sections() {
-    extern "C" {
-        static mut __noncacheable_data_start__: u32;
-        static mut __noncacheable_data_end__: u32;
-        static __noncacheable_data_load_addr__: u32;
-        static mut __noncacheable_bss_start__: u32;
-        static mut __noncacheable_bss_end__: u32;
-    }
-
-    // Copy .noncacheable.data
-    let mut src = core::ptr::addr_of!(__noncacheable_data_load_addr__) as *const u32;
-    let mut dst = core::ptr::addr_of_mut!(__noncacheable_data_start__);
-    let end = core::ptr::addr_of!(__noncacheable_data_end__) as *const u32;
-    while (dst as *const u32) < end {
-        dst.write_volatile(src.read_volatile());
-        src = src.add(1);
-        dst = dst.add(1);
+/// Lock PMP entry 0 over the null-pointer guard at the bottom of ILM.
+///
+/// The guard is reserved by `hpm-link.x` when `_null_guard_size` is set in
+/// memory.x. The entry grants no permissions and is locked, so it also applies
+/// to M-mode: null loads, stores and calls raise `LoadFault`, `StoreFault` and
+/// `InstructionFault`. See [`trap::is_null_pointer_fault`].
+unsafe fn configure_null_guard() {
+    let size = null_guard_size();
+    if size == 0 {
+        return;
     }
 
-    // Zero .noncacheable.bss
-    let mut dst = core::ptr::addr_of_mut!(__noncacheable_bss_start__);
-    let end = core::ptr::addr_of!(__noncacheable_bss_end__) as *const u32;
-    while (dst as *const u32) < end {
-        dst.write_volatile(0);
-        dst = dst.add(1);
-    }
+    // NAPOT address format: (base + size/2 - 1) >> 2, base is 0.
+    // Size is checked to be a power of two >= 8 at link time.
+    pmpaddr0::write(((size >> 1) - 1) >> 2);
+    pmpcfg0::set_pmp(0, Range::NAPOT, Permission::NONE, true);
 }
 
-/// Configure PMA for HPM67xx: both RTT fix and noncacheable region in one call.
+/// Size of the null-pointer guard (`_null_guard_size`), 0 if disabled.
 ///
-/// This avoids potential issues with separate pmacfg0 modifications.
-/// - Entry 0: RTT control block (4KB)
-/// - Entry 1: REGION_NONCACHEABLE_RAM
-#[cfg(all(feature = "hpm67-fix", feature = "pma-noncacheable"))]
-unsafe fn configure_pma_hpm67() {
-    // RTT symbol (weak, 0 if not linked)
-    extern "C" {
-        #[link_name = "_SEGGER_RTT"]
-        static SEGGER_RTT: u8;
-        static __noncacheable_start__: u32;
-        static __noncacheable_end__: u32;
-    }
-
-    let rtt_addr = core::ptr::addr_of!(SEGGER_RTT) as u32;
-    let nc_start = core::ptr::addr_of!(__noncacheable_start__) as u32;
-    let nc_end = core::ptr::addr_of!(__noncacheable_end__) as u32;
-
-    // PMA entry format (8 bits each):
-    // [1:0] ETYP: 0=OFF, 1=TOR, 2=NA4, 3=NAPOT
-    // [4:2] MTYP: 0=Device, 2=Non-cacheable non-bufferable, 3=Non-cacheable bufferable
-    // [5]   AMO:  Atomic operations
-    // [7:6] Reserved
-    const ENTRY_NAPOT_NC_BUF: u32 = 0x0F; // ETYP=NAPOT(3), MTYP=NC_BUF(3), AMO=0
-    const ENTRY_NAPOT_NC_BUF_AMO: u32 = 0x2F; // ETYP=NAPOT(3), MTYP=NC_BUF(3), AMO=1
-
-    let mut pmacfg0_val: u32 = 0;
-
-    // Entry 0: RTT (4KB)
-    if rtt_addr != 0 {
-        let aligned_addr = rtt_addr & !0xFFF;
-        let size = 0x1000u32; // 4KB
-        let napot_addr = (aligned_addr + (size >> 1) - 1) >> 2;
-        register::pmaaddr0::write(napot_addr as usize);
-        pmacfg0_val |= ENTRY_NAPOT_NC_BUF; // Entry 0 in bits [7:0]
-    }
-
-    // Entry 1: Noncacheable region
-    if nc_end > nc_start {
-        let length = nc_end - nc_start;
-        let napot_addr = (nc_start + (length >> 1) - 1) >> 2;
-        register::pmaaddr1::write(napot_addr as usize);
-        pmacfg0_val |= ENTRY_NAPOT_NC_BUF_AMO << 8; // Entry 1 in bits [15:8]
+/// Loaded with `lui`/`addi`: the compiler assumes the address of an extern
+/// static is never 0, so `addr_of!` could not be compared against 0.
+#[inline(always)]
+pub(crate) fn null_guard_size() -> usize {
+    let size: usize;
+    // SAFETY: only materializes an absolute symbol value.
+    unsafe {
+        core::arch::asm!(
+            "lui {0}, %hi(_null_guard_size)",
+            "addi {0}, {0}, %lo(_null_guard_size)",
+            out(reg) size,
+            options(pure, nomem, nostack)
+        );
     }
-
-    // Write pmacfg0 directly using CSR instruction
-    core::arch::asm!(
-        "csrw 0xBC0, {0}",  // pmacfg0 = 0xBC0
-        in(reg) pmacfg0_val,
-        options(nomem, nostack)
-    );
-
-    // Fence to ensure PMA takes effect
-    core::arch::asm!("fence.i");
+    size
 }
 
-/// Configure PMA to make RTT control block non-cacheable (HPM67xx D-cache fix).
-///
-/// This function detects if defmt-rtt is linked by checking if `_SEGGER_RTT` symbol
-/// exists (linker provides 0 if not defined). If linked, it configures PMA entry 0
-/// to make that region non-cacheable, solving the D-cache coherency issue with probe-rs RTT.
+/// Configure PMA entries for the `.rtt` block (`hpm67-fix`),
+/// `__noncacheable_start__..__noncacheable_end__` and
+/// `REGION_SDRAM_NONCACHEABLE` (`pma-noncacheable`).
 ///
-/// PMA configuration:
-/// - Entry type: NAPOT (Naturally Aligned Power Of Two)
-/// - Memory type: Non-cacheable, Bufferable
-/// - Region size: 4KB (aligned down from _SEGGER_RTT address)
-#[cfg(all(feature = "hpm67-fix", not(feature = "pma-noncacheable")))]
-unsafe fn configure_rtt_noncacheable() {
-    // Weak symbol - will be null/zero if defmt-rtt is not linked
-    extern "C" {
-        #[link_name = "_SEGGER_RTT"]
-        static SEGGER_RTT: u8;
+/// Both are checked to be NAPOT-encodable at link time.
+#[cfg(all(
+    any(feature = "hpm67-fix", feature = "pma-noncacheable"),
+    not(feature = "emulator")
+))]
+unsafe fn configure_pma() {
+    let mut pma = pma::Builder::new();
+
+    // HPM67xx D-cache fix: make the RTT control block and buffers
+    // non-cacheable, so probe-rs sees what defmt-rtt writes.
+    #[cfg(feature = "hpm67-fix")]
+    {
+        extern "C" {
+            static __rtt_start__: u32;
+            static __rtt_end__: u32;
+            static __rtt_block_end__: u32;
+        }
+
+        // Skipped if nothing was placed in .rtt
+        let start = core::ptr::addr_of!(__rtt_start__) as usize;
+        let end = core::ptr::addr_of!(__rtt_end__) as usize;
+        let block_end = core::ptr::addr_of!(__rtt_block_end__) as usize;
+        if end > start {
+            pma = pma.noncacheable(start, block_end - start);
+        }
     }
 
-    // Get the address of _SEGGER_RTT
-    let rtt_addr = core::ptr::addr_of!(SEGGER_RTT) as u32;
-
-    // Skip if symbol doesn't exist (address would be 0 or invalid)
-    if rtt_addr == 0 {
-        return;
+    // REGION_NONCACHEABLE_RAM (skipped if empty, e.g. HPM5300)
+    #[cfg(feature = "pma-noncacheable")]
+    {
+        extern "C" {
+            static __noncacheable_start__: u32;
+            static __noncacheable_end__: u32;
+        }
+
+        let start = core::ptr::addr_of!(__noncacheable_start__) as usize;
+        let end = core::ptr::addr_of!(__noncacheable_end__) as usize;
+        if end > start {
+            pma = pma.noncacheable(start, end - start);
+        }
     }
 
-    // Align down to 4KB boundary (PMA NAPOT minimum practical granularity)
-    let aligned_addr = rtt_addr & !0xFFF;
-    let size = 0x1000u32; // 4KB
-
-    // NAPOT address format: (base + size/2 - 1) >> 2
-    let napot_addr = (aligned_addr + (size >> 1) - 1) >> 2;
-
-    // Configure PMA entry 0 to make RTT region non-cacheable
-    // ENTRY_NAPOT_NC_BUF = 0x0F: ETYP=NAPOT(3), MTYP=NC_BUF(3), AMO=0
-    register::pmaaddr0::write(napot_addr as usize);
-    core::arch::asm!(
-        "csrw 0xBC0, {0}",  // pmacfg0 = 0xBC0
-        in(reg) 0x0Fu32,
-        options(nomem, nostack)
-    );
+    // REGION_SDRAM_NONCACHEABLE (skipped without hpm-sdram.x, or if it
+    // aliases REGION_SDRAM)
+    #[cfg(feature = "pma-noncacheable")]
+    {
+        extern "C" {
+            static __sdram_noncacheable_start__: u32;
+            static __sdram_noncacheable_end__: u32;
+        }
+
+        let start = core::ptr::addr_of!(__sdram_noncacheable_start__) as usize;
+        let end = core::ptr::addr_of!(__sdram_noncacheable_end__) as usize;
+        if end > start {
+            pma = pma.noncacheable(start, end - start);
+        }
+    }
 
-    // Fence to ensure PMA takes effect
-    core::arch::asm!("fence.i");
+    // Startup uses at most 3 of the 16 entries
+    pma.apply()
+        .expect("not enough PMA entries for .rtt and the non-cacheable regions");
 }
 
-/// Configure PMA to make REGION_NONCACHEABLE_RAM actually non-cacheable.
+/// Configure PMP from the linker symbols, see [`pmp::Builder::from_linker`].
 ///
-/// This function reads the linker-provided `__noncacheable_start__` and `__noncacheable_end__`
-/// symbols and configures PMA entry 1 to make that region non-cacheable.
-///
-/// Required for HPM5E/62/63 series. For HPM67xx, use configure_pma_hpm67() instead.
-#[cfg(all(feature = "pma-noncacheable", not(feature = "hpm67-fix")))]
-unsafe fn configure_noncacheable_pma() {
-    extern "C" {
-        static __noncacheable_start__: u32;
-        static __noncacheable_end__: u32;
-    }
-
-    let start = core::ptr::addr_of!(__noncacheable_start__) as u32;
-    let end = core::ptr::addr_of!(__noncacheable_end__) as u32;
-
-    // Skip if noncacheable region is empty (e.g., HPM5300)
-    if end <= start {
-        return;
-    }
-
-    let length = end - start;
-
-    // Verify alignment requirements (must be power of 2 aligned)
-    debug_assert!(
-        (length & (length - 1)) == 0,
-        "noncacheable region length must be power of 2"
-    );
-    debug_assert!(
-        (start & (length - 1)) == 0,
-        "noncacheable region start must be aligned to its size"
-    );
-
-    // NAPOT address format: (base + size/2 - 1) >> 2
-    let napot_addr = (start + (length >> 1) - 1) >> 2;
-
-    // Configure PMA entry 1 to make noncacheable region non-cacheable
-    // ENTRY_NAPOT_NC_BUF_AMO = 0x2F: ETYP=NAPOT(3), MTYP=NC_BUF(3), AMO=1
-    register::pmaaddr1::write(napot_addr as usize);
-    core::arch::asm!(
-        "csrw 0xBC0, {0}",  // pmacfg0 = 0xBC0, Entry 1 in bits [15:8]
-        in(reg) (0x2Fu32 << 8),
-        options(nomem, nostack)
-    );
-
-    // Fence to ensure PMA takes effect
-    core::arch::asm!("fence.i");
+/// With `pmp-lock` the entries are locked and also apply to M-mode.
+#[cfg(feature = "pmp")]
+unsafe fn configure_pmp() {
+    let pmp = pmp::Builder::from_linker();
+    #[cfg(feature = "pmp-lock")]
+    let pmp = pmp.locked();
+    pmp.apply()
+        .expect("not enough PMP entries for the linker regions");
 }
 
 // ============ Interrupt Setup ============
@@ -342,16 +377,23 @@ unsafe fn configure_noncacheable_pma() {
 /// This function:
 /// 1. Cleans up PLIC state
 /// 2. Enables mcycle counter
-/// 3. Configures mtvec to point to the vector table
-/// 4. Enables PLIC vectored mode via MMISC_CTL
-/// 5. Enables global interrupts
+/// 3. Configures mtvec to point to the vector table (`CORE_LOCAL` with
+///    `emulator`, `user-mode` or `supervisor-mode`)
+/// 4. Enables PLIC vectored mode via MMISC_CTL (not with `emulator`,
+///    `user-mode` or `supervisor-mode`)
+/// 5. Enables global interrupts (not with `keep-interrupts-disabled`)
+///
+/// # Safety
+///
+/// Reprograms `mtvec` and the PLIC. Called once from `_hpm_start_rust`.
 #[export_name = "_setup_interrupts"]
 pub unsafe fn setup_interrupts() {
     extern "C" {
-        // Vector table generated by hpm-metapac
+        // Start of .vector_table in REGION_VECTOR, which holds __INTERRUPTS
+        // (generated by hpm-metapac):
         // Entry 0: CORE_LOCAL (exceptions and core interrupts)
         // Entry 1+: PLIC external interrupt handlers
-        static __INTERRUPTS: u32;
+        static __vector_ram_start__: u32;
     }
 
     let plic = Plic::from_ptr(PLIC_BASE as *mut ());
@@ -372,18 +414,47 @@ pub unsafe fn setup_interrupts() {
     mcounteren::set_cy();
 
     // 3. Set vector table address
-    let vector_addr = core::ptr::addr_of!(__INTERRUPTS) as usize;
+    #[cfg(not(any(
+        feature = "emulator",
+        feature = "user-mode",
+        feature = "supervisor-mode"
+    )))]
+    let vector_addr = core::ptr::addr_of!(__vector_ram_start__) as usize;
+    // Direct mode: CORE_LOCAL takes every trap and dispatches external
+    // interrupts through the vector table itself. With user-mode or
+    // supervisor-mode, this keeps handlers off the application's stack.
+    #[cfg(any(
+        feature = "emulator",
+        feature = "user-mode",
+        feature = "supervisor-mode"
+    ))]
+    let vector_addr = {
+        extern "C" {
+            fn CORE_LOCAL();
+        }
+        CORE_LOCAL as *const () as usize
+    };
     // Note: TrapMode is ignored by hardware when MMISC_CTL.VEC_PLIC is set
     let mtvec_val = Mtvec::new(vector_addr, TrapMode::Direct);
     mtvec::write(mtvec_val);
 
     // 4. Enable PLIC vectored mode (Andes-specific)
-    plic.feature().modify(|w| w.set_vectored(true));
-    register::mmisc_ctl::set_vec_plic();
+    #[cfg(not(any(
+        feature = "emulator",
+        feature = "user-mode",
+        feature = "supervisor-mode"
+    )))]
+    {
+        plic.feature().modify(|w| w.set_vectored(true));
+        andes_riscv::register::mmisc_ctl::set_vec_plic();
+    }
 
-    // 5. Enable global interrupts
-    mstatus::set_mie();
-    mstatus::set_sie();
+    // 5. Enable global interrupts, unless the application does it
+    #[cfg(not(feature = "keep-interrupts-disabled"))]
+    {
+        mstatus::set_mie();
+        mstatus::set_sie();
+    }
     mie::set_mext();
 }
 
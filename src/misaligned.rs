@@ -0,0 +1,496 @@
+//! Software emulation of misaligned loads and stores (`emulate-misaligned`
+//! feature).
+//!
+//! Cores that do not handle misaligned accesses in hardware raise
+//! `LoadMisaligned` / `StoreMisaligned`, e.g. for fields of packed protocol
+//! structs or from C code. With this feature, `CORE_LOCAL` decodes the
+//! instruction at `mepc`, performs the access byte by byte, writes the result
+//! into the interrupted context and resumes after the instruction. Supported
+//! instructions:
+//!
+//! - `lb`, `lh`, `lw`, `lbu`, `lhu`, `sb`, `sh`, `sw`
+//! - `c.lw`, `c.sw`, `c.lwsp`, `c.swsp`
+//! - `flw`, `fsw`, `c.flw`, `c.fsw`, `c.flwsp`, `c.fswsp` (with the F
+//!   extension)
+//! - `fld`, `fsd`, `c.fld`, `c.fsd`, `c.fldsp`, `c.fsdsp` (with the D
+//!   extension)
+//!
+//! Anything else, including loads into `sp`, still goes to the
+//! `LoadMisaligned` / `StoreMisaligned` handlers.
+//!
+//! The byte accesses use `mstatus.MPRV`, so they are checked with the
+//! privilege of the interrupted code. If one of them faults, the access fault
+//! is reported from inside the emulator.
+//!
+//! Each emulated access takes a trap, so it is much slower than an aligned
+//! one. [`count`] and [`last_pc`] help to find and fix the hot spots:
+//!
+//! ```ignore
+//! if hpm_riscv_rt::misaligned::count() > 0 {
+//!     println!("misaligned access at {:#x}", hpm_riscv_rt::misaligned::last_pc());
+//! }
+//! ```
+//!
+//! With `supervisor-mode`, misaligned accesses are not delegated to S-mode,
+//! so they are emulated in M-mode as well.
+
+use core::arch::{asm, global_asm};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use riscv::register::mepc;
+
+use crate::TrapFrame;
+
+/// `mstatus.MPRV`
+const MPRV: usize = 1 << 17;
+
+static COUNT: AtomicUsize = AtomicUsize::new(0);
+static LAST_PC: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of misaligned loads and stores emulated since reset.
+pub fn count() -> usize {
+    COUNT.load(Ordering::Relaxed)
+}
+
+/// Address of the most recently emulated instruction, 0 if none.
+pub fn last_pc() -> usize {
+    LAST_PC.load(Ordering::Relaxed)
+}
+
+/// Registers that `CORE_LOCAL` does not save in the [`TrapFrame`].
+#[repr(C)]
+struct Saved {
+    /// Return address into `CORE_LOCAL`
+    _ra: usize,
+    gp: usize,
+    tp: usize,
+    s0: usize,
+    s1: usize,
+    /// s2..s11 (x18..x27)
+    s2_11: [usize; 10],
+}
+
+// Called from CORE_LOCAL for mcause 4 and 6 with a0 = TrapFrame and a1 = sp
+// at the trap. Saves the remaining integer registers, so that every register
+// can be read and written, and returns a0 != 0 if the access was emulated.
+global_asm!(
+    r#"
+    .section .trap.rust, "ax"
+    .global __hpm_emulate_misaligned
+    .type __hpm_emulate_misaligned, @function
+    .balign 4
+
+__hpm_emulate_misaligned:
+    addi sp, sp, -(16 * 4)
+    sw ra, 0(sp)
+    sw gp, 4(sp)
+    sw tp, 8(sp)
+    sw s0, 12(sp)
+    sw s1, 16(sp)
+    sw s2, 20(sp)
+    sw s3, 24(sp)
+    sw s4, 28(sp)
+    sw s5, 32(sp)
+    sw s6, 36(sp)
+    sw s7, 40(sp)
+    sw s8, 44(sp)
+    sw s9, 48(sp)
+    sw s10, 52(sp)
+    sw s11, 56(sp)
+
+    mv a2, a1
+    mv a1, sp
+    call _hpm_emulate_misaligned_rust
+
+    /* Pick up loads into these registers */
+    lw gp, 4(sp)
+    lw tp, 8(sp)
+    lw s0, 12(sp)
+    lw s1, 16(sp)
+    lw s2, 20(sp)
+    lw s3, 24(sp)
+    lw s4, 28(sp)
+    lw s5, 32(sp)
+    lw s6, 36(sp)
+    lw s7, 40(sp)
+    lw s8, 44(sp)
+    lw s9, 48(sp)
+    lw s10, 52(sp)
+    lw s11, 56(sp)
+    lw ra, 0(sp)
+    addi sp, sp, 16 * 4
+    ret
+
+    .size __hpm_emulate_misaligned, . - __hpm_emulate_misaligned
+"#
+);
+
+/// A register operand.
+#[derive(Clone, Copy)]
+enum Reg {
+    X(usize),
+    #[cfg_attr(not(target_feature = "f"), allow(dead_code))]
+    F(usize),
+    #[cfg_attr(not(target_feature = "d"), allow(dead_code))]
+    D(usize),
+}
+
+/// A decoded load or store.
+struct Access {
+    load: bool,
+    /// Destination of a load, source of a store
+    reg: Reg,
+    base: usize,
+    offset: usize,
+    width: usize,
+    signed: bool,
+    /// Instruction length
+    len: usize,
+}
+
+/// Registers of the interrupted context.
+struct Context<'a> {
+    frame: &'a mut TrapFrame,
+    saved: &'a mut Saved,
+    sp: usize,
+}
+
+impl Context<'_> {
+    fn read(&self, x: usize) -> usize {
+        let f = &*self.frame;
+        let s = &*self.saved;
+        match x {
+            1 => f.ra,
+            2 => self.sp,
+            3 => s.gp,
+            4 => s.tp,
+            5 => f.t0,
+            6 => f.t1,
+            7 => f.t2,
+            8 => s.s0,
+            9 => s.s1,
+            10 => f.a0,
+            11 => f.a1,
+            12 => f.a2,
+            13 => f.a3,
+            14 => f.a4,
+            15 => f.a5,
+            16 => f.a6,
+            17 => f.a7,
+            18..=27 => s.s2_11[x - 18],
+            28 => f.t3,
+            29 => f.t4,
+            30 => f.t5,
+            31 => f.t6,
+            _ => 0,
+        }
+    }
+
+    /// Write `x`. Writes to `zero` and `sp` are dropped.
+    fn write(&mut self, x: usize, value: usize) {
+        let f = &mut *self.frame;
+        let s = &mut *self.saved;
+        let reg = match x {
+            0 | 2 => return,
+            1 => &mut f.ra,
+            3 => &mut s.gp,
+            4 => &mut s.tp,
+            5 => &mut f.t0,
+            6 => &mut f.t1,
+            7 => &mut f.t2,
+            8 => &mut s.s0,
+            9 => &mut s.s1,
+            10 => &mut f.a0,
+            11 => &mut f.a1,
+            12 => &mut f.a2,
+            13 => &mut f.a3,
+            14 => &mut f.a4,
+            15 => &mut f.a5,
+            16 => &mut f.a6,
+            17 => &mut f.a7,
+            18..=27 => &mut s.s2_11[x - 18],
+            28 => &mut f.t3,
+            29 => &mut f.t4,
+            30 => &mut f.t5,
+            _ => &mut f.t6,
+        };
+        *reg = value;
+    }
+}
+
+/// Emulate the misaligned access at `mepc`. Returns false to dispatch the
+/// exception as usual.
+#[no_mangle]
+#[link_section = ".trap.rust"]
+unsafe extern "C" fn _hpm_emulate_misaligned_rust(
+    frame: &mut TrapFrame,
+    saved: &mut Saved,
+    sp: usize,
+) -> bool {
+    let pc = mepc::read();
+    let Some(access) = decode(pc) else {
+        return false;
+    };
+    // CORE_LOCAL restores sp itself
+    if access.load && matches!(access.reg, Reg::X(2)) {
+        return false;
+    }
+    let mut ctx = Context { frame, saved, sp };
+    let addr = ctx.read(access.base).wrapping_add(access.offset);
+
+    if access.load {
+        let mut value = 0u64;
+        for i in 0..access.width {
+            value |= (load_byte(addr.wrapping_add(i)) as u64) << (8 * i);
+        }
+        if access.signed && access.width < 4 {
+            let shift = 64 - 8 * access.width;
+            value = (((value << shift) as i64) >> shift) as u64;
+        }
+        match access.reg {
+            Reg::X(rd) => ctx.write(rd, value as usize),
+            #[cfg(target_feature = "f")]
+            Reg::F(rd) => write_f(rd, value as u32),
+            #[cfg(not(target_feature = "f"))]
+            Reg::F(_) => return false,
+            #[cfg(target_feature = "d")]
+            Reg::D(rd) => write_d(rd, value),
+            #[cfg(not(target_feature = "d"))]
+            Reg::D(_) => return false,
+        }
+    } else {
+        let value = match access.reg {
+            Reg::X(rs2) => ctx.read(rs2) as u64,
+            #[cfg(target_feature = "f")]
+            Reg::F(rs2) => read_f(rs2) as u64,
+            #[cfg(not(target_feature = "f"))]
+            Reg::F(_) => return false,
+            #[cfg(target_feature = "d")]
+            Reg::D(rs2) => read_d(rs2),
+            #[cfg(not(target_feature = "d"))]
+            Reg::D(_) => return false,
+        };
+        for i in 0..access.width {
+            store_byte(addr.wrapping_add(i), (value >> (8 * i)) as u8);
+        }
+    }
+
+    COUNT.fetch_add(1, Ordering::Relaxed);
+    LAST_PC.store(pc, Ordering::Relaxed);
+    mepc::write(pc + access.len);
+    true
+}
+
+/// Decode the load or store at `pc`.
+unsafe fn decode(pc: usize) -> Option<Access> {
+    // Instructions are only 2-byte aligned with the C extension
+    let p = pc as *const u16;
+    let low = p.read_volatile() as u32;
+    if low & 3 == 3 {
+        decode_32(low | (p.add(1).read_volatile() as u32) << 16)
+    } else {
+        decode_16(low)
+    }
+}
+
+fn decode_32(inst: u32) -> Option<Access> {
+    let opcode = inst & 0x7f;
+    let funct3 = (inst >> 12) & 7;
+    let rd = ((inst >> 7) & 0x1f) as usize;
+    let rs1 = ((inst >> 15) & 0x1f) as usize;
+    let rs2 = ((inst >> 20) & 0x1f) as usize;
+    // Sign-extended 12-bit immediates
+    let i_imm = ((inst as i32) >> 20) as usize;
+    let s_imm = (((inst as i32) >> 25) << 5 | ((inst >> 7) & 0x1f) as i32) as usize;
+
+    let (load, reg, offset, width, signed) = match (opcode, funct3) {
+        // LOAD: lb, lh, lw, lbu, lhu
+        (0x03, 0) => (true, Reg::X(rd), i_imm, 1, true),
+        (0x03, 1) => (true, Reg::X(rd), i_imm, 2, true),
+        (0x03, 2) => (true, Reg::X(rd), i_imm, 4, false),
+        (0x03, 4) => (true, Reg::X(rd), i_imm, 1, false),
+        (0x03, 5) => (true, Reg::X(rd), i_imm, 2, false),
+        // STORE: sb, sh, sw
+        (0x23, 0) => (false, Reg::X(rs2), s_imm, 1, false),
+        (0x23, 1) => (false, Reg::X(rs2), s_imm, 2, false),
+        (0x23, 2) => (false, Reg::X(rs2), s_imm, 4, false),
+        // LOAD-FP / STORE-FP: flw, fsw
+        #[cfg(target_feature = "f")]
+        (0x07, 2) => (true, Reg::F(rd), i_imm, 4, false),
+        #[cfg(target_feature = "f")]
+        (0x27, 2) => (false, Reg::F(rs2), s_imm, 4, false),
+        // LOAD-FP / STORE-FP: fld, fsd
+        #[cfg(target_feature = "d")]
+        (0x07, 3) => (true, Reg::D(rd), i_imm, 8, false),
+        #[cfg(target_feature = "d")]
+        (0x27, 3) => (false, Reg::D(rs2), s_imm, 8, false),
+        _ => return None,
+    };
+    Some(Access {
+        load,
+        reg,
+        base: rs1,
+        offset,
+        width,
+        signed,
+        len: 4,
+    })
+}
+
+fn decode_16(inst: u32) -> Option<Access> {
+    let quadrant = inst & 3;
+    let funct3 = (inst >> 13) & 7;
+    // rd'/rs2' and rs1' of the CL/CS formats
+    let reg_low = 8 + ((inst >> 2) & 7) as usize;
+    let rs1_low = 8 + ((inst >> 7) & 7) as usize;
+    // offset[5:3|2|6] of c.lw/c.sw
+    let cl_offset = ((inst >> 10) & 7) << 3 | ((inst >> 6) & 1) << 2 | ((inst >> 5) & 1) << 6;
+    // offset[5|4:2|7:6] of c.lwsp
+    let lwsp_offset = ((inst >> 12) & 1) << 5 | ((inst >> 4) & 7) << 2 | ((inst >> 2) & 3) << 6;
+    // offset[5:2|7:6] of c.swsp
+    let swsp_offset = ((inst >> 9) & 0xf) << 2 | ((inst >> 7) & 3) << 6;
+    // offset[5:3|7:6] of c.fld/c.fsd
+    #[cfg(target_feature = "d")]
+    let cld_offset = ((inst >> 10) & 7) << 3 | ((inst >> 5) & 3) << 6;
+    // offset[5|4:3|8:6] of c.fldsp
+    #[cfg(target_feature = "d")]
+    let ldsp_offset = ((inst >> 12) & 1) << 5 | ((inst >> 5) & 3) << 3 | ((inst >> 2) & 7) << 6;
+    // offset[5:3|8:6] of c.fsdsp
+    #[cfg(target_feature = "d")]
+    let sdsp_offset = ((inst >> 10) & 7) << 3 | ((inst >> 7) & 7) << 6;
+    let rd = ((inst >> 7) & 0x1f) as usize;
+    let rs2 = ((inst >> 2) & 0x1f) as usize;
+
+    let (load, reg, base, offset, width) = match (quadrant, funct3) {
+        // c.lw, c.sw
+        (0, 2) => (true, Reg::X(reg_low), rs1_low, cl_offset, 4),
+        (0, 6) => (false, Reg::X(reg_low), rs1_low, cl_offset, 4),
+        // c.flw, c.fsw
+        #[cfg(target_feature = "f")]
+        (0, 3) => (true, Reg::F(reg_low), rs1_low, cl_offset, 4),
+        #[cfg(target_feature = "f")]
+        (0, 7) => (false, Reg::F(reg_low), rs1_low, cl_offset, 4),
+        // c.fld, c.fsd
+        #[cfg(target_feature = "d")]
+        (0, 1) => (true, Reg::D(reg_low), rs1_low, cld_offset, 8),
+        #[cfg(target_feature = "d")]
+        (0, 5) => (false, Reg::D(reg_low), rs1_low, cld_offset, 8),
+        // c.lwsp (rd != 0), c.swsp
+        (2, 2) if rd != 0 => (true, Reg::X(rd), 2, lwsp_offset, 4),
+        (2, 6) => (false, Reg::X(rs2), 2, swsp_offset, 4),
+        // c.flwsp, c.fswsp
+        #[cfg(target_feature = "f")]
+        (2, 3) => (true, Reg::F(rd), 2, lwsp_offset, 4),
+        #[cfg(target_feature = "f")]
+        (2, 7) => (false, Reg::F(rs2), 2, swsp_offset, 4),
+        // c.fldsp, c.fsdsp
+        #[cfg(target_feature = "d")]
+        (2, 1) => (true, Reg::D(rd), 2, ldsp_offset, 8),
+        #[cfg(target_feature = "d")]
+        (2, 5) => (false, Reg::D(rs2), 2, sdsp_offset, 8),
+        _ => return None,
+    };
+    Some(Access {
+        load,
+        reg,
+        base,
+        offset: offset as usize,
+        width,
+        signed: false,
+        len: 2,
+    })
+}
+
+/// Load a byte with the privilege of the interrupted code.
+#[inline(always)]
+unsafe fn load_byte(addr: usize) -> u8 {
+    let value: u8;
+    asm!(
+        "csrs mstatus, {mprv}",
+        "lbu {value}, 0({addr})",
+        "csrc mstatus, {mprv}",
+        mprv = in(reg) MPRV,
+        addr = in(reg) addr,
+        value = out(reg) value,
+        options(nostack),
+    );
+    value
+}
+
+/// Store a byte with the privilege of the interrupted code.
+#[inline(always)]
+unsafe fn store_byte(addr: usize, value: u8) {
+    asm!(
+        "csrs mstatus, {mprv}",
+        "sb {value}, 0({addr})",
+        "csrc mstatus, {mprv}",
+        mprv = in(reg) MPRV,
+        addr = in(reg) addr,
+        value = in(reg) value,
+        options(nostack),
+    );
+}
+
+// One `fmv` per FP register, since the register number is part of the
+// instruction.
+macro_rules! fp_access {
+    ($($n:literal)*) => {
+        #[cfg(target_feature = "f")]
+        unsafe fn read_f(reg: usize) -> u32 {
+            let value: u32;
+            match reg {
+                $($n => asm!(
+                    concat!("fmv.x.w {}, f", $n),
+                    out(reg) value,
+                    options(nomem, nostack),
+                ),)*
+                _ => unreachable!(),
+            }
+            value
+        }
+
+        #[cfg(target_feature = "f")]
+        unsafe fn write_f(reg: usize, value: u32) {
+            match reg {
+                $($n => asm!(
+                    concat!("fmv.w.x f", $n, ", {}"),
+                    in(reg) value,
+                    options(nomem, nostack),
+                ),)*
+                _ => unreachable!(),
+            }
+        }
+
+        // RV32 has no 64-bit `fmv`, so doubles go through memory
+        #[cfg(target_feature = "d")]
+        unsafe fn read_d(reg: usize) -> u64 {
+            let mut value = 0u64;
+            match reg {
+                $($n => asm!(
+                    concat!("fsd f", $n, ", 0({})"),
+                    in(reg) core::ptr::addr_of_mut!(value),
+                    options(nostack),
+                ),)*
+                _ => unreachable!(),
+            }
+            value
+        }
+
+        #[cfg(target_feature = "d")]
+        unsafe fn write_d(reg: usize, value: u64) {
+            match reg {
+                $($n => asm!(
+                    concat!("fld f", $n, ", 0({})"),
+                    in(reg) core::ptr::addr_of!(value),
+                    options(nostack, readonly),
+                ),)*
+                _ => unreachable!(),
+            }
+        }
+    };
+}
+
+fp_access!(
+    0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15
+    16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31
+);
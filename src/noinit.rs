@@ -0,0 +1,99 @@
+//! Persistent RAM that survives warm reset.
+//!
+//! Statics marked with [`noinit`](crate::noinit) go into the `.noinit`
+//! section in `REGION_NOINIT`. Startup code never copies or zeroes it, so its
+//! contents are whatever the previous boot (or a bootloader) left there.
+//!
+//! Use [`Persistent`] to tell valid data from power-on garbage:
+//!
+//! ```ignore
+//! use hpm_riscv_rt::{noinit, noinit::Persistent};
+//!
+//! #[noinit]
+//! static mut BOOT_COUNT: Persistent<u32> = Persistent::uninit();
+//!
+//! let count = unsafe { &mut *core::ptr::addr_of_mut!(BOOT_COUNT) };
+//! let n = count.get().unwrap_or(0) + 1;
+//! count.set(n);
+//! ```
+//!
+//! To hand data between a bootloader and an application, both images must use
+//! the same `REGION_NOINIT` and the same layout. Keep everything in a single
+//! `#[noinit]` static so the linker cannot reorder it.
+
+use core::mem::{size_of, MaybeUninit};
+
+const MAGIC: u32 = 0x4850_4D50; // "HPMP"
+
+/// A value in persistent RAM, validated with a magic word and a CRC-32.
+///
+/// `T` must not contain padding bytes, since the CRC covers its raw bytes.
+#[repr(C)]
+pub struct Persistent<T: Copy> {
+    magic: u32,
+    crc: u32,
+    value: MaybeUninit<T>,
+}
+
+impl<T: Copy> Persistent<T> {
+    /// Create an empty value. Used as the initializer of a `#[noinit]` static,
+    /// which is never actually applied at runtime.
+    pub const fn uninit() -> Self {
+        Self {
+            magic: 0,
+            crc: 0,
+            value: MaybeUninit::uninit(),
+        }
+    }
+
+    /// Return the stored value if the magic word and CRC are valid.
+    pub fn get(&self) -> Option<T> {
+        if self.is_valid() {
+            // SAFETY: a valid magic and CRC means `set` wrote the value.
+            Some(unsafe { self.value.assume_init() })
+        } else {
+            None
+        }
+    }
+
+    /// Store a value and update the magic word and CRC.
+    pub fn set(&mut self, value: T) {
+        self.value.write(value);
+        self.crc = self.compute_crc();
+        self.magic = Self::magic();
+    }
+
+    /// Mark the stored value as invalid.
+    pub fn invalidate(&mut self) {
+        // SAFETY: `self.magic` is a valid, aligned pointer.
+        unsafe { core::ptr::write_volatile(&mut self.magic, 0) };
+    }
+
+    /// Check the magic word and CRC.
+    pub fn is_valid(&self) -> bool {
+        // SAFETY: `self.magic` is a valid, aligned pointer.
+        let magic = unsafe { core::ptr::read_volatile(&self.magic) };
+        magic == Self::magic() && self.crc == self.compute_crc()
+    }
+
+    /// Magic word, mixed with the size of `T` to catch layout changes.
+    const fn magic() -> u32 {
+        MAGIC ^ (size_of::<T>() as u32)
+    }
+
+    fn compute_crc(&self) -> u32 {
+        let ptr = self.value.as_ptr() as *const u8;
+        let mut crc = 0xFFFF_FFFFu32;
+        for i in 0..size_of::<T>() {
+            // SAFETY: within `self.value`, read volatile since memory may
+            // have been written by a previous boot.
+            let byte = unsafe { core::ptr::read_volatile(ptr.add(i)) };
+            crc ^= byte as u32;
+            for _ in 0..8 {
+                let mask = (crc & 1).wrapping_neg();
+                crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+            }
+        }
+        !crc
+    }
+}
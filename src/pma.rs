@@ -0,0 +1,323 @@
+//! Physical Memory Attributes (Andes PMA).
+//!
+//! PMA entries override the default memory type of an address range, e.g. to
+//! make a RAM region non-cacheable for DMA. An [`Entry`] is built with `const`
+//! constructors that check NAPOT size and alignment, so a misaligned region in
+//! a `const` is a compile error. A [`Builder`] collects entries and
+//! [`Builder::apply`] writes them to the first free hardware entries.
+//!
+//! ```ignore
+//! use hpm_riscv_rt::pma::{self, Entry, MemoryType};
+//!
+//! const PMA: pma::Builder = pma::Builder::new()
+//!     .noncacheable(0x010C_0000, 256 * 1024) // AXI SRAM
+//!     .noncacheable(0x41C0_0000, 4 * 1024 * 1024) // SDRAM
+//!     .entry(Entry::napot(0x0008_0000, 4096, MemoryType::NonCacheable).no_amo());
+//!
+//! unsafe { PMA.apply().unwrap() };
+//! ```
+//!
+//! With the `pma-noncacheable` and `hpm67-fix` features, `_hpm_start_rust`
+//! already uses this module for `__noncacheable_start__..__noncacheable_end__`
+//! and the `.rtt` block. Entries set up there are left alone by later
+//! `apply` calls.
+
+use andes_riscv::register;
+
+/// Number of PMA entries.
+pub const NUM_ENTRIES: usize = 16;
+
+/// Smallest NAPOT region supported by the PMA.
+pub const GRANULARITY: usize = 4096;
+
+/// Entry address matching mode (`ETYP`).
+///
+/// HPMicro cores implement `Off` and `Napot`. `Tor` and `Na4` are the
+/// standard encodings, kept for cores that support them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum EntryType {
+    /// Entry disabled
+    Off = 0,
+    /// Top of range, the previous entry's address is the bottom
+    Tor = 1,
+    /// Naturally aligned 4-byte region
+    Na4 = 2,
+    /// Naturally aligned power-of-two region
+    Napot = 3,
+}
+
+/// Memory type attribute (`MTYP`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MemoryType {
+    /// Device memory, non-bufferable
+    Device = 0,
+    /// Device memory, bufferable
+    DeviceBufferable = 1,
+    /// Normal memory, non-cacheable, non-bufferable
+    NonCacheable = 2,
+    /// Normal memory, non-cacheable, bufferable
+    NonCacheableBufferable = 3,
+    /// Normal memory, write-through, no allocate
+    WriteThrough = 4,
+    /// Normal memory, write-through, read allocate
+    WriteThroughReadAllocate = 5,
+    /// Normal memory, write-back, no allocate
+    WriteBack = 8,
+    /// Normal memory, write-back, read allocate
+    WriteBackReadAllocate = 9,
+    /// Normal memory, write-back, write allocate
+    WriteBackWriteAllocate = 10,
+    /// Normal memory, write-back, read and write allocate
+    WriteBackReadWriteAllocate = 11,
+    /// Empty hole, accesses fault
+    EmptyHole = 15,
+}
+
+/// A PMA entry: address match, memory type and AMO support.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Entry {
+    etyp: EntryType,
+    addr: usize,
+    mtyp: MemoryType,
+    amo: bool,
+}
+
+impl Entry {
+    /// A disabled entry.
+    pub const fn off() -> Self {
+        Self {
+            etyp: EntryType::Off,
+            addr: 0,
+            mtyp: MemoryType::Device,
+            amo: true,
+        }
+    }
+
+    /// `size` bytes at `base`. `size` must be a power of two of at least
+    /// [`GRANULARITY`], and `base` must be aligned to `size`.
+    pub const fn napot(base: usize, size: usize, mtyp: MemoryType) -> Self {
+        assert!(
+            size >= GRANULARITY && size.is_power_of_two(),
+            "PMA NAPOT size must be a power of two of at least 4 KiB"
+        );
+        assert!(
+            base & (size - 1) == 0,
+            "PMA NAPOT base must be aligned to its size"
+        );
+        Self {
+            etyp: EntryType::Napot,
+            // NAPOT address format: (base + size/2 - 1) >> 2
+            addr: (base + (size >> 1) - 1) >> 2,
+            mtyp,
+            amo: true,
+        }
+    }
+
+    /// The 4 bytes at `addr`, which must be 4-byte aligned.
+    pub const fn na4(addr: usize, mtyp: MemoryType) -> Self {
+        assert!(addr & 3 == 0, "PMA NA4 address must be 4-byte aligned");
+        Self {
+            etyp: EntryType::Na4,
+            addr: addr >> 2,
+            mtyp,
+            amo: true,
+        }
+    }
+
+    /// From the previous entry's address up to `top` (exclusive), which must
+    /// be 4-byte aligned.
+    pub const fn tor(top: usize, mtyp: MemoryType) -> Self {
+        assert!(top & 3 == 0, "PMA TOR address must be 4-byte aligned");
+        Self {
+            etyp: EntryType::Tor,
+            addr: top >> 2,
+            mtyp,
+            amo: true,
+        }
+    }
+
+    /// Mark atomic memory operations (including LR/SC) as unsupported.
+    pub const fn no_amo(mut self) -> Self {
+        self.amo = false;
+        self
+    }
+
+    /// Address matching mode.
+    pub const fn entry_type(&self) -> EntryType {
+        self.etyp
+    }
+
+    /// Memory type.
+    pub const fn memory_type(&self) -> MemoryType {
+        self.mtyp
+    }
+
+    /// Encoded `pmaaddr` value.
+    pub const fn addr(&self) -> usize {
+        self.addr
+    }
+
+    /// Encoded `pmacfg` byte: `[1:0]` ETYP, `[5:2]` MTYP, `[6]` NAMO.
+    pub const fn cfg(&self) -> u8 {
+        self.etyp as u8 | (self.mtyp as u8) << 2 | (!self.amo as u8) << 6
+    }
+}
+
+/// Error returned by [`Builder::apply`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// Not enough free hardware entries. Nothing was written.
+    NoFreeEntry,
+}
+
+/// A set of PMA entries, assigned to free hardware entries by [`apply`](Self::apply).
+#[derive(Clone, Copy, Debug)]
+pub struct Builder {
+    entries: [Entry; NUM_ENTRIES],
+    len: usize,
+}
+
+impl Builder {
+    /// An empty set.
+    pub const fn new() -> Self {
+        Self {
+            entries: [Entry::off(); NUM_ENTRIES],
+            len: 0,
+        }
+    }
+
+    /// Add an entry.
+    ///
+    /// `Tor` entries use the address of the hardware entry before them, so
+    /// only use them on an otherwise unused PMA.
+    pub const fn entry(mut self, entry: Entry) -> Self {
+        assert!(self.len < NUM_ENTRIES, "too many PMA entries");
+        self.entries[self.len] = entry;
+        self.len += 1;
+        self
+    }
+
+    /// Add a non-cacheable, bufferable NAPOT region with AMO support.
+    pub const fn noncacheable(self, base: usize, size: usize) -> Self {
+        self.entry(Entry::napot(base, size, MemoryType::NonCacheableBufferable))
+    }
+
+    /// Entries added so far.
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries[..self.len]
+    }
+
+    /// Write the entries to the first free (`Off`) hardware entries, in
+    /// order, then fence so the new attributes take effect.
+    ///
+    /// # Safety
+    ///
+    /// Changes the memory type of the given regions. Cached data in a region
+    /// that becomes non-cacheable must be written back beforehand (see
+    /// [`cache::dcache_flush`](crate::cache::dcache_flush)), and code
+    /// must not rely on atomics in regions marked with [`Entry::no_amo`].
+    ///
+    /// Does nothing with the `emulator` feature.
+    pub unsafe fn apply(&self) -> Result<(), Error> {
+        // QEMU has no PMA CSRs, and no caches to bypass
+        if cfg!(feature = "emulator") {
+            return Ok(());
+        }
+        let mut cfg = read_cfg();
+
+        // Assign all entries before touching the hardware
+        let mut slots = [0usize; NUM_ENTRIES];
+        let mut next = 0;
+        for slot in slots.iter_mut().take(self.len) {
+            while next < NUM_ENTRIES && cfg_byte(&cfg, next) & 0x03 != 0 {
+                next += 1;
+            }
+            if next == NUM_ENTRIES {
+                return Err(Error::NoFreeEntry);
+            }
+            *slot = next;
+            next += 1;
+        }
+
+        for (entry, &slot) in self.entries().iter().zip(&slots) {
+            write_addr(slot, entry.addr);
+            cfg[slot / 4] |= (entry.cfg() as u32) << ((slot % 4) * 8);
+        }
+
+        write_cfg(&cfg);
+
+        // Make the new attributes visible to data and instruction accesses
+        core::arch::asm!("fence iorw, iorw", "fence.i");
+        Ok(())
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// pmacfg0..3 are accessed directly: andes-riscv 0.3 maps all of them to 0xBC0.
+fn read_cfg() -> [u32; 4] {
+    let cfg: [u32; 4];
+    // SAFETY: reading PMA configuration CSRs has no side effects.
+    unsafe {
+        let (c0, c1, c2, c3): (u32, u32, u32, u32);
+        core::arch::asm!(
+            "csrr {0}, 0xBC0",
+            "csrr {1}, 0xBC1",
+            "csrr {2}, 0xBC2",
+            "csrr {3}, 0xBC3",
+            out(reg) c0,
+            out(reg) c1,
+            out(reg) c2,
+            out(reg) c3,
+            options(nomem, nostack)
+        );
+        cfg = [c0, c1, c2, c3];
+    }
+    cfg
+}
+
+unsafe fn write_cfg(cfg: &[u32; 4]) {
+    core::arch::asm!(
+        "csrw 0xBC0, {0}",
+        "csrw 0xBC1, {1}",
+        "csrw 0xBC2, {2}",
+        "csrw 0xBC3, {3}",
+        in(reg) cfg[0],
+        in(reg) cfg[1],
+        in(reg) cfg[2],
+        in(reg) cfg[3],
+        options(nomem, nostack)
+    );
+}
+
+fn cfg_byte(cfg: &[u32; 4], index: usize) -> u8 {
+    (cfg[index / 4] >> ((index % 4) * 8)) as u8
+}
+
+unsafe fn write_addr(index: usize, addr: usize) {
+    match index {
+        0 => register::pmaaddr0::write(addr),
+        1 => register::pmaaddr1::write(addr),
+        2 => register::pmaaddr2::write(addr),
+        3 => register::pmaaddr3::write(addr),
+        4 => register::pmaaddr4::write(addr),
+        5 => register::pmaaddr5::write(addr),
+        6 => register::pmaaddr6::write(addr),
+        7 => register::pmaaddr7::write(addr),
+        8 => register::pmaaddr8::write(addr),
+        9 => register::pmaaddr9::write(addr),
+        10 => register::pmaaddr10::write(addr),
+        11 => register::pmaaddr11::write(addr),
+        12 => register::pmaaddr12::write(addr),
+        13 => register::pmaaddr13::write(addr),
+        14 => register::pmaaddr14::write(addr),
+        15 => register::pmaaddr15::write(addr),
+        _ => unreachable!(),
+    }
+}
@@ -0,0 +1,401 @@
+//! Physical Memory Protection.
+//!
+//! A [`Builder`] collects address ranges with permissions and
+//! [`Builder::apply`] turns them into TOR entries after the last PMP entry in
+//! use. Ranges are sorted, adjacent ranges with the same permissions are
+//! merged, and a range starting where the previous one ends shares its
+//! boundary entry.
+//!
+//! Unlocked entries only restrict S-mode and U-mode. [`Builder::locked`]
+//! entries also apply to M-mode and cannot be changed until reset. Accesses
+//! outside every range are allowed in M-mode and denied in S/U-mode, unless
+//! [`allow_remaining`] adds a catch-all entry.
+//!
+//! With the `pmp` feature, `_hpm_start_rust` protects the image described by
+//! the linker script (see [`Builder::from_linker`]); `pmp-lock` locks those
+//! entries. Applications can add their own ranges afterwards:
+//!
+//! ```ignore
+//! use hpm_riscv_rt::pmp::{self, Permission};
+//!
+//! // Read-only flash config pages
+//! const PMP: pmp::Builder = pmp::Builder::new()
+//!     .region(0x800F_E000, 0x8010_0000, Permission::R)
+//!     .locked();
+//!
+//! unsafe { PMP.apply().unwrap() };
+//! ```
+//!
+//! Later entries have lower priority, so added ranges must not overlap the
+//! ones set up at startup.
+
+use riscv::register::{
+    pmpaddr0, pmpaddr1, pmpaddr10, pmpaddr11, pmpaddr12, pmpaddr13, pmpaddr14, pmpaddr15, pmpaddr2,
+    pmpaddr3, pmpaddr4, pmpaddr5, pmpaddr6, pmpaddr7, pmpaddr8, pmpaddr9, pmpcfg0, pmpcfg1,
+    pmpcfg2, pmpcfg3,
+};
+pub use riscv::register::{Permission, Range};
+
+/// Number of PMP entries.
+pub const NUM_ENTRIES: usize = 16;
+
+/// Maximum number of ranges in a [`Builder`].
+pub const MAX_REGIONS: usize = 16;
+
+const CFG_LOCK: u8 = 1 << 7;
+
+/// An address range `start..end` with its permissions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region {
+    /// Start address, 4-byte aligned
+    pub start: usize,
+    /// End address (exclusive), 4-byte aligned
+    pub end: usize,
+    /// Allowed accesses
+    pub permission: Permission,
+}
+
+/// Error returned by [`Builder::apply`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// Not enough free PMP entries. Nothing was written.
+    NoFreeEntry,
+}
+
+/// A set of PMP ranges.
+#[derive(Clone, Copy, Debug)]
+pub struct Builder {
+    regions: [Region; MAX_REGIONS],
+    len: usize,
+    locked: bool,
+}
+
+impl Builder {
+    /// An empty set.
+    pub const fn new() -> Self {
+        Self {
+            regions: [Region {
+                start: 0,
+                end: 0,
+                permission: Permission::NONE,
+            }; MAX_REGIONS],
+            len: 0,
+            locked: false,
+        }
+    }
+
+    /// Add the range `start..end`. Empty ranges are ignored.
+    pub const fn region(mut self, start: usize, end: usize, permission: Permission) -> Self {
+        assert!(
+            start & 3 == 0 && end & 3 == 0,
+            "PMP range must be 4-byte aligned"
+        );
+        assert!(self.len < MAX_REGIONS, "too many PMP ranges");
+        self.regions[self.len] = Region {
+            start,
+            end,
+            permission,
+        };
+        self.len += 1;
+        self
+    }
+
+    /// Lock the entries, so they also apply to M-mode.
+    pub const fn locked(mut self) -> Self {
+        self.locked = true;
+        self
+    }
+
+    /// Ranges added so far.
+    pub fn regions(&self) -> &[Region] {
+        &self.regions[..self.len]
+    }
+
+    /// The image described by the linker script:
+    ///
+    /// - `.text` (`_stext.._etext`) and `.fast` text: read/execute
+    /// - `.rodata` (`_srodata.._erodata`): read-only
+    /// - `.data`, `.bss`, `.fast.data`, `.fast.bss`, `.noncacheable.*` and
+    ///   the [`dma`](crate::dma) pool, `.rtt`, heap and stack: read/write,
+    ///   not executable
+    ///
+    /// Read/write ranges in the same memory region (e.g. `.fast.data`, heap
+    /// and stack in DLM) are merged with the space between them, so each
+    /// region takes at most two entries. `hpm-link.x` checks at link time
+    /// that the ranges fit in the PMP entries.
+    ///
+    /// With `user-mode` or `supervisor-mode`, the M-mode trap stack at the
+    /// top of `.stack` gets no permissions, so U-mode or S-mode cannot access
+    /// it.
+    ///
+    /// `.noinit`, `.ahb_sram` and the `.sdram.*` sections are not covered, to
+    /// keep PMP entries free, so S-mode and U-mode code must not use them
+    /// unless the application adds ranges for them, or they share a memory
+    /// region with the ranges above and lie between them.
+    pub fn from_linker() -> Self {
+        extern "C" {
+            static _stext: u8;
+            static _etext: u8;
+            static __fast_text_start__: u8;
+            static __fast_text_end__: u8;
+            static _srodata: u8;
+            static _erodata: u8;
+            static _sdata: u8;
+            static _edata: u8;
+            static _sbss: u8;
+            static _ebss: u8;
+            static __fast_data_start__: u8;
+            static __fast_bss_end__: u8;
+            static __noncacheable_data_start__: u8;
+            static __noncacheable_pool_end__: u8;
+            static __rtt_start__: u8;
+            static __rtt_end__: u8;
+            static _sheap: u8;
+            static _ec_heap: u8;
+            static _estack: u8;
+            static _sstack: u8;
+            static __data_region_origin__: u8;
+            static __bss_region_origin__: u8;
+            static __fastdata_region_origin__: u8;
+            static __noncacheable_region_origin__: u8;
+            static __heap_region_origin__: u8;
+            static __stack_region_origin__: u8;
+        }
+
+        macro_rules! range {
+            ($start:ident, $end:ident) => {
+                (
+                    core::ptr::addr_of!($start) as usize,
+                    core::ptr::addr_of!($end) as usize,
+                )
+            };
+        }
+        // Origin of a memory region. Behind `black_box`, since the compiler
+        // assumes distinct symbols have distinct addresses, while aliased
+        // regions share their origin.
+        macro_rules! region {
+            ($origin:ident) => {
+                Some(core::hint::black_box(core::ptr::addr_of!($origin) as usize))
+            };
+        }
+
+        #[cfg(not(any(feature = "user-mode", feature = "supervisor-mode")))]
+        let stack_top = core::ptr::addr_of!(_sstack) as usize;
+        #[cfg(any(feature = "user-mode", feature = "supervisor-mode"))]
+        let stack_top = crate::stack::app_stack_top();
+
+        // Each range with the memory region it may be merged within
+        let mut ranges = [
+            (range!(_stext, _etext), Permission::RX, None),
+            (
+                range!(__fast_text_start__, __fast_text_end__),
+                Permission::RX,
+                None,
+            ),
+            (range!(_srodata, _erodata), Permission::R, None),
+            (
+                range!(_sdata, _edata),
+                Permission::RW,
+                region!(__data_region_origin__),
+            ),
+            (
+                range!(_sbss, _ebss),
+                Permission::RW,
+                region!(__bss_region_origin__),
+            ),
+            (
+                range!(__fast_data_start__, __fast_bss_end__),
+                Permission::RW,
+                region!(__fastdata_region_origin__),
+            ),
+            (
+                range!(__noncacheable_data_start__, __noncacheable_pool_end__),
+                Permission::RW,
+                region!(__noncacheable_region_origin__),
+            ),
+            (
+                range!(__rtt_start__, __rtt_end__),
+                Permission::RW,
+                region!(__noncacheable_region_origin__),
+            ),
+            (
+                range!(_sheap, _ec_heap),
+                Permission::RW,
+                region!(__heap_region_origin__),
+            ),
+            (
+                (core::ptr::addr_of!(_estack) as usize, stack_top),
+                Permission::RW,
+                region!(__stack_region_origin__),
+            ),
+            // Also keeps the ranges around it from being merged over it
+            #[cfg(any(feature = "user-mode", feature = "supervisor-mode"))]
+            (
+                (stack_top, core::ptr::addr_of!(_sstack) as usize),
+                Permission::NONE,
+                region!(__stack_region_origin__),
+            ),
+        ];
+        ranges.sort_unstable_by_key(|((start, _), _, _)| *start);
+
+        let mut builder = Self::new();
+        let mut last_region = None;
+        for ((start, end), permission, region) in ranges {
+            if end <= start {
+                continue;
+            }
+            match builder.len.checked_sub(1).map(|i| &mut builder.regions[i]) {
+                Some(last)
+                    if region.is_some()
+                        && region == last_region
+                        && permission == last.permission =>
+                {
+                    last.end = end;
+                }
+                _ => builder = builder.region(start, end, permission),
+            }
+            last_region = region;
+        }
+        builder
+    }
+
+    /// Write the ranges to the PMP entries after the last one in use.
+    ///
+    /// # Safety
+    ///
+    /// Restricts memory accesses. Locked entries apply to M-mode too, so
+    /// everything the firmware still needs to execute, read or write must be
+    /// covered with the right permissions.
+    pub unsafe fn apply(&self) -> Result<(), Error> {
+        let mut regions = self.regions;
+        let regions = &mut regions[..self.len];
+        regions.sort_unstable_by_key(|r| r.start);
+
+        let mut cfg = [
+            pmpcfg0::read().bits as u32,
+            pmpcfg1::read().bits as u32,
+            pmpcfg2::read().bits as u32,
+            pmpcfg3::read().bits as u32,
+        ];
+        let first = first_free(&cfg);
+
+        // Plan all entries before touching the hardware
+        let lock = if self.locked { CFG_LOCK } else { 0 };
+        let mut addr = [0usize; NUM_ENTRIES];
+        let mut byte = [0u8; NUM_ENTRIES];
+        let mut next = first;
+        let mut top: Option<(usize, Permission)> = None;
+        for r in regions.iter().filter(|r| r.end > r.start) {
+            match top {
+                // Same permissions and adjacent: extend the previous entry
+                Some((end, perm)) if end == r.start && perm == r.permission => {
+                    addr[next - 1] = r.end >> 2;
+                }
+                _ => {
+                    // TOR starts at the previous entry's address
+                    if top.map(|(end, _)| end) != Some(r.start) {
+                        if next == NUM_ENTRIES {
+                            return Err(Error::NoFreeEntry);
+                        }
+                        addr[next] = r.start >> 2;
+                        byte[next] = 0;
+                        next += 1;
+                    }
+                    if next == NUM_ENTRIES {
+                        return Err(Error::NoFreeEntry);
+                    }
+                    addr[next] = r.end >> 2;
+                    byte[next] = lock | (Range::TOR as u8) << 3 | r.permission as u8;
+                    next += 1;
+                }
+            }
+            top = Some((r.end, r.permission));
+        }
+
+        for i in first..next {
+            write_addr(i, addr[i]);
+            cfg[i / 4] |= (byte[i] as u32) << ((i % 4) * 8);
+        }
+        pmpcfg0::write(cfg[0] as usize);
+        pmpcfg1::write(cfg[1] as usize);
+        pmpcfg2::write(cfg[2] as usize);
+        pmpcfg3::write(cfg[3] as usize);
+
+        core::arch::asm!("fence iorw, iorw");
+        Ok(())
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Give S-mode and U-mode `permission` to everything not covered by an
+/// earlier entry, with a NAPOT entry spanning the whole address space after
+/// the last one in use.
+///
+/// # Safety
+///
+/// Lifts the default deny for S/U-mode. Must be the last entry written.
+pub unsafe fn allow_remaining(permission: Permission) -> Result<(), Error> {
+    let index = first_free(&[
+        pmpcfg0::read().bits as u32,
+        pmpcfg1::read().bits as u32,
+        pmpcfg2::read().bits as u32,
+        pmpcfg3::read().bits as u32,
+    ]);
+    if index == NUM_ENTRIES {
+        return Err(Error::NoFreeEntry);
+    }
+    // All ones: NAPOT over the whole 34-bit physical address space
+    write_addr(index, usize::MAX);
+    set_cfg(index, Range::NAPOT, permission);
+    core::arch::asm!("fence iorw, iorw");
+    Ok(())
+}
+
+/// First entry after the last one with its address matching mode set.
+fn first_free(cfg: &[u32; 4]) -> usize {
+    (0..NUM_ENTRIES)
+        .rev()
+        .find(|&i| cfg_byte(cfg, i) & 0x18 != 0)
+        .map_or(0, |i| i + 1)
+}
+
+unsafe fn set_cfg(index: usize, range: Range, permission: Permission) {
+    match index / 4 {
+        0 => pmpcfg0::set_pmp(index % 4, range, permission, false),
+        1 => pmpcfg1::set_pmp(index % 4, range, permission, false),
+        2 => pmpcfg2::set_pmp(index % 4, range, permission, false),
+        3 => pmpcfg3::set_pmp(index % 4, range, permission, false),
+        _ => unreachable!(),
+    }
+}
+
+fn cfg_byte(cfg: &[u32; 4], index: usize) -> u8 {
+    (cfg[index / 4] >> ((index % 4) * 8)) as u8
+}
+
+unsafe fn write_addr(index: usize, addr: usize) {
+    match index {
+        0 => pmpaddr0::write(addr),
+        1 => pmpaddr1::write(addr),
+        2 => pmpaddr2::write(addr),
+        3 => pmpaddr3::write(addr),
+        4 => pmpaddr4::write(addr),
+        5 => pmpaddr5::write(addr),
+        6 => pmpaddr6::write(addr),
+        7 => pmpaddr7::write(addr),
+        8 => pmpaddr8::write(addr),
+        9 => pmpaddr9::write(addr),
+        10 => pmpaddr10::write(addr),
+        11 => pmpaddr11::write(addr),
+        12 => pmpaddr12::write(addr),
+        13 => pmpaddr13::write(addr),
+        14 => pmpaddr14::write(addr),
+        15 => pmpaddr15::write(addr),
+        _ => unreachable!(),
+    }
+}
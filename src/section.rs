@@ -0,0 +1,111 @@
+//! Startup copy/zero tables.
+//!
+//! `hpm-link.x` emits two tables in `REGION_RODATA`:
+//! - `__copy_table_start__..__copy_table_end__`: [`CopyEntry`] records
+//! - `__zero_table_start__..__zero_table_end__`: [`ZeroEntry`] records
+//!
+//! `_hpm_start` walks both tables right after `__pre_init`, so every section
+//! listed there is ready before `_hpm_start_rust` runs. The built-in entries
+//! (.data, .vector_table, .fast, .fast.data, .noncacheable.data, .bss, .fast.bss,
+//! .noncacheable.bss) always come first.
+//!
+//! ## Registering extra sections
+//!
+//! From a linker fragment, insert an output section of records right after the
+//! table. Keep it in its own `SECTIONS` block so nothing else lands between
+//! the records:
+//!
+//! ```ld
+//! SECTIONS
+//! {
+//!     .axi_sram.data : ALIGN(4)
+//!     {
+//!         __axi_data_start__ = .;
+//!         *(.axi_sram.data .axi_sram.data.*);
+//!         . = ALIGN(4);
+//!         __axi_data_end__ = .;
+//!     } > AXI_SRAM AT > REGION_RODATA
+//! }
+//! INSERT AFTER .data;
+//!
+//! SECTIONS
+//! {
+//!     .copy_table.axi_sram :
+//!     {
+//!         LONG(LOADADDR(.axi_sram.data)) LONG(__axi_data_start__) LONG(__axi_data_end__ - __axi_data_start__)
+//!     } > REGION_RODATA
+//! }
+//! INSERT AFTER .copy_table;
+//! ```
+//!
+//! From Rust, use [`init_section`](crate::init_section) on a static.
+//!
+//! ## SDRAM tables
+//!
+//! `hpm-sdram.x` emits a second pair of tables
+//! (`__sdram_copy_table_start__`, `__sdram_zero_table_start__`, ...) for the
+//! `.sdram.*` sections. They are walked by `_hpm_start_rust` after the
+//! `__sdram_init` hook, since SDRAM is not usable before FEMC is configured.
+
+/// One record of the copy table: copy `len` bytes from `src` to `dst`.
+#[repr(C)]
+pub struct CopyEntry {
+    /// Load address (initial image in flash)
+    pub src: *const u8,
+    /// Run address
+    pub dst: *mut u8,
+    /// Length in bytes
+    pub len: usize,
+}
+
+// SAFETY: entries are immutable records only read by the startup code.
+unsafe impl Sync for CopyEntry {}
+
+/// One record of the zero table: zero `len` bytes at `dst`.
+#[repr(C)]
+pub struct ZeroEntry {
+    /// Run address
+    pub dst: *mut u8,
+    /// Length in bytes
+    pub len: usize,
+}
+
+// SAFETY: entries are immutable records only read by the startup code.
+unsafe impl Sync for ZeroEntry {}
+
+/// Initial image of a static registered with `#[init_section]`.
+///
+/// Only its address is ever used, as the `src` of a [`CopyEntry`].
+#[doc(hidden)]
+#[repr(transparent)]
+pub struct InitImage<T>(pub T);
+
+// SAFETY: the image is never accessed through Rust references.
+unsafe impl<T> Sync for InitImage<T> {}
+
+extern "C" {
+    fn _hpm_run_copy_table(start: *const CopyEntry, end: *const CopyEntry);
+    fn _hpm_run_zero_table(start: *const ZeroEntry, end: *const ZeroEntry);
+}
+
+/// Initialize the SDRAM sections from the tables emitted by `hpm-sdram.x`.
+///
+/// Both tables are empty when `hpm-sdram.x` is not linked.
+#[inline(always)]
+pub(crate) unsafe fn init_sdram_sections() {
+    extern "C" {
+        static __sdram_copy_table_start__: CopyEntry;
+        static __sdram_copy_table_end__: CopyEntry;
+        static __sdram_zero_table_start__: ZeroEntry;
+        static __sdram_zero_table_end__: ZeroEntry;
+    }
+
+    _hpm_run_copy_table(
+        core::ptr::addr_of!(__sdram_copy_table_start__),
+        core::ptr::addr_of!(__sdram_copy_table_end__),
+    );
+    _hpm_run_zero_table(
+        core::ptr::addr_of!(__sdram_zero_table_start__),
+        core::ptr::addr_of!(__sdram_zero_table_end__),
+    );
+}
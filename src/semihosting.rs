@@ -0,0 +1,196 @@
+//! RISC-V semihosting (`semihosting` feature).
+//!
+//! Semihosting calls are `ebreak`s with a magic sequence around them, which a
+//! debugger (OpenOCD, probe-rs) or emulator (`qemu -semihosting`) services on
+//! behalf of the target: console output, host files and exiting with a status
+//! code, e.g. for tests run in CI.
+//!
+//! ```ignore
+//! use core::fmt::Write;
+//! use hpm_riscv_rt::semihosting;
+//!
+//! writeln!(semihosting::Console, "booted").ok();
+//! semihosting::exit(0);
+//! ```
+//!
+//! Without a debugger the `ebreak` traps to M-mode. `CORE_LOCAL` (and
+//! `S_TRAP` with `supervisor-mode`) recognize the sequence and step over
+//! it, so the call fails with `-1` instead of reaching the `Breakpoint`
+//! handler. Other `ebreak`s are dispatched as before.
+//!
+//! With `c-runtime`, C `printf` output can go to the host with
+//! `c_runtime::set_sink(semihosting::write)`.
+
+use core::arch::global_asm;
+use core::ffi::CStr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::TrapFrame;
+
+const SYS_OPEN: usize = 0x01;
+const SYS_CLOSE: usize = 0x02;
+const SYS_WRITE: usize = 0x05;
+const SYS_READ: usize = 0x06;
+const SYS_EXIT_EXTENDED: usize = 0x20;
+
+/// `ADP_Stopped_ApplicationExit`, the reason passed to `SYS_EXIT_EXTENDED`.
+const APPLICATION_EXIT: usize = 0x20026;
+
+/// `slli x0, x0, 0x1f`
+const ENTRY_NOP: u32 = 0x01f0_1013;
+/// `ebreak`
+const EBREAK: u32 = 0x0010_0073;
+/// `srai x0, x0, 7`
+const EXIT_NOP: u32 = 0x4070_5013;
+
+// The sequence must be uncompressed and must not cross a page, so it lives
+// in its own aligned function.
+global_asm!(
+    r#"
+    .section .text.__hpm_semihosting_call, "ax"
+    .global __hpm_semihosting_call
+    .type __hpm_semihosting_call, @function
+    .balign 16
+
+__hpm_semihosting_call:
+    .option push
+    .option norvc
+    slli x0, x0, 0x1f
+    ebreak
+    srai x0, x0, 7
+    .option pop
+    ret
+
+    .size __hpm_semihosting_call, . - __hpm_semihosting_call
+"#
+);
+
+/// Issue semihosting operation `op` with parameter `param` (usually the
+/// address of a parameter block) and return the host's result.
+///
+/// # Safety
+///
+/// `param` must be valid for `op`, see the semihosting specification.
+pub unsafe fn call(op: usize, param: usize) -> usize {
+    extern "C" {
+        fn __hpm_semihosting_call(op: usize, param: usize) -> usize;
+    }
+
+    __hpm_semihosting_call(op, param)
+}
+
+/// Open mode of a host file, as in `fopen`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(usize)]
+pub enum Mode {
+    /// `"r"`
+    Read = 0,
+    /// `"rb"`
+    ReadBinary = 1,
+    /// `"w"`
+    Write = 4,
+    /// `"wb"`
+    WriteBinary = 5,
+    /// `"a"`
+    Append = 8,
+    /// `"ab"`
+    AppendBinary = 9,
+}
+
+/// A file on the host.
+pub struct File {
+    handle: usize,
+}
+
+impl File {
+    /// Open `path` on the host. `":tt"` is the host console.
+    pub fn open(path: &CStr, mode: Mode) -> Option<File> {
+        let block = [path.as_ptr() as usize, mode as usize, path.to_bytes().len()];
+        // SAFETY: the block matches SYS_OPEN.
+        let handle = unsafe { call(SYS_OPEN, block.as_ptr() as usize) };
+        (handle != usize::MAX).then_some(File { handle })
+    }
+
+    /// Write `bytes`, returning how many were written.
+    pub fn write(&self, bytes: &[u8]) -> usize {
+        write_handle(self.handle, bytes)
+    }
+
+    /// Read into `buf`, returning how many bytes were read (0 at end of
+    /// file).
+    pub fn read(&self, buf: &mut [u8]) -> usize {
+        let block = [self.handle, buf.as_mut_ptr() as usize, buf.len()];
+        // SAFETY: the block matches SYS_READ. Returns the bytes not read.
+        let left = unsafe { call(SYS_READ, block.as_ptr() as usize) };
+        buf.len().saturating_sub(left)
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        let block = [self.handle];
+        // SAFETY: the block matches SYS_CLOSE.
+        unsafe { call(SYS_CLOSE, block.as_ptr() as usize) };
+    }
+}
+
+fn write_handle(handle: usize, bytes: &[u8]) -> usize {
+    let block = [handle, bytes.as_ptr() as usize, bytes.len()];
+    // SAFETY: the block matches SYS_WRITE. Returns the bytes not written.
+    let left = unsafe { call(SYS_WRITE, block.as_ptr() as usize) };
+    bytes.len().saturating_sub(left)
+}
+
+/// Host console handle plus one, 0 until opened.
+static CONSOLE: AtomicUsize = AtomicUsize::new(0);
+
+/// Write `bytes` to the host console.
+pub fn write(bytes: &[u8]) {
+    let mut console = CONSOLE.load(Ordering::Relaxed);
+    if console == 0 {
+        let Some(file) = File::open(c":tt", Mode::Write) else {
+            return;
+        };
+        // Kept open for good
+        console = file.handle + 1;
+        core::mem::forget(file);
+        CONSOLE.store(console, Ordering::Relaxed);
+    }
+    write_handle(console - 1, bytes);
+}
+
+/// The host console, for `write!`.
+pub struct Console;
+
+impl core::fmt::Write for Console {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        write(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// End the session with exit code `status`. Without a debugger, spins.
+pub fn exit(status: i32) -> ! {
+    let block = [APPLICATION_EXIT, status as usize];
+    // SAFETY: the block matches SYS_EXIT_EXTENDED.
+    unsafe { call(SYS_EXIT_EXTENDED, block.as_ptr() as usize) };
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+/// Step over a semihosting sequence whose `ebreak` trapped at `pc` because
+/// no debugger is attached. The call fails with `-1`. Returns the pc after
+/// the sequence, or `None` if the `ebreak` at `pc` is not a semihosting call.
+pub(crate) unsafe fn skip(frame: &mut TrapFrame, pc: usize) -> Option<usize> {
+    // Instructions are only 2-byte aligned with the C extension
+    let read = |addr: usize| {
+        let p = addr as *const u16;
+        p.read_volatile() as u32 | (p.add(1).read_volatile() as u32) << 16
+    };
+    if read(pc) != EBREAK || read(pc - 4) != ENTRY_NOP || read(pc + 4) != EXIT_NOP {
+        return None;
+    }
+    frame.a0 = usize::MAX;
+    Some(pc + 8)
+}
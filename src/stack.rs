@@ -0,0 +1,92 @@
+//! Stack bounds, usage and high-water mark.
+//!
+//! `.stack` is `_estack.._sstack`: exactly `_stack_size` bytes ending at
+//! `_stack_start`. The linker script fails if it would overlap `.heap` or
+//! `.bss`.
+//!
+//! With the `stack-paint` feature, `_hpm_start` fills the stack with
+//! [`PAINT`] before anything uses it, and [`high_water_mark`] finds the
+//! deepest word that was overwritten since. Each core of a dual-core part
+//! runs its own image with its own `.stack`, so the numbers are per hart.
+//!
+//! ```ignore
+//! use hpm_riscv_rt::stack;
+//!
+//! defmt::info!("stack: {} of {} bytes, peak {}",
+//!     stack::usage(), stack::size(), stack::high_water_mark());
+//! ```
+
+/// Pattern written over the stack by `stack-paint`.
+pub const PAINT: u32 = 0xCDCD_CDCD;
+
+/// M-mode stack at the top of `.stack`, used by `CORE_LOCAL` for traps from
+/// U-mode (`user-mode`) or S-mode (`supervisor-mode`).
+#[cfg(any(feature = "user-mode", feature = "supervisor-mode"))]
+pub const TRAP_STACK_SIZE: usize = 2048;
+
+/// Stack range `bottom..top`. The stack grows down from `top`.
+pub fn bounds() -> (usize, usize) {
+    extern "C" {
+        static _estack: u32;
+        static _sstack: u32;
+    }
+
+    (
+        core::ptr::addr_of!(_estack) as usize,
+        core::ptr::addr_of!(_sstack) as usize,
+    )
+}
+
+/// Stack size in bytes (`_stack_size`).
+pub fn size() -> usize {
+    let (bottom, top) = bounds();
+    top - bottom
+}
+
+/// Bytes currently in use, from the top of the stack to `sp`.
+///
+/// With `user-mode` or `supervisor-mode`, usage on the application's stack
+/// is measured from its top, so the M-mode trap stack above it is not
+/// counted.
+#[inline(always)]
+pub fn usage() -> usize {
+    let sp: usize;
+    // SAFETY: only reads the stack pointer.
+    unsafe { core::arch::asm!("mv {0}, sp", out(reg) sp, options(nomem, nostack)) };
+    let (_, top) = bounds();
+    #[cfg(any(feature = "user-mode", feature = "supervisor-mode"))]
+    let top = if sp <= app_stack_top() {
+        app_stack_top()
+    } else {
+        top
+    };
+    top.saturating_sub(sp)
+}
+
+/// Peak stack usage in bytes since reset: the distance from the top of the
+/// stack to the lowest word that no longer holds [`PAINT`].
+///
+/// Scans from the bottom of the stack, so it takes time proportional to the
+/// unused part.
+#[cfg(feature = "stack-paint")]
+pub fn high_water_mark() -> usize {
+    let (bottom, top) = bounds();
+    let mut addr = bottom;
+    // SAFETY: `bottom..top` is the stack, word aligned.
+    while addr < top && unsafe { (addr as *const u32).read_volatile() } == PAINT {
+        addr += 4;
+    }
+    top - addr
+}
+
+/// Top of the M-mode trap stack, the top of `.stack`.
+#[cfg(any(feature = "user-mode", feature = "supervisor-mode"))]
+pub(crate) fn trap_stack_top() -> usize {
+    bounds().1
+}
+
+/// Top of the U-mode or S-mode stack, right below the M-mode trap stack.
+#[cfg(any(feature = "user-mode", feature = "supervisor-mode"))]
+pub(crate) fn app_stack_top() -> usize {
+    trap_stack_top() - TRAP_STACK_SIZE
+}
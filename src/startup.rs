@@ -0,0 +1,85 @@
+//! Ordered init functions registered with `#[init]`.
+//!
+//! Any crate can register startup code for one of three stages:
+//!
+//! - `pre_ram`: from `_hpm_start`, after `__pre_init` and before `.data` and
+//!   `.bss` are initialized. Only the stack may be used.
+//! - `post_ram`: from `_hpm_start_rust`, after all RAM sections (including
+//!   `.sdram.*`) are initialized, caches still disabled
+//! - `pre_main`: right before `main`, after interrupts are set up (still in
+//!   M-mode with `user-mode` and `supervisor-mode`)
+//!
+//! ```ignore
+//! #[hpm_riscv_rt::init(stage = pre_ram, priority = 10)]
+//! unsafe fn disable_watchdog() { /* ... */ }
+//!
+//! #[hpm_riscv_rt::init(stage = post_ram, priority = 100)]
+//! unsafe fn clocks() { /* ... */ }
+//! ```
+//!
+//! Each registration is a function pointer in `.hpm_init.<stage>.<priority>`,
+//! which `hpm-link.x` sorts by name into
+//! `__hpm_init_<stage>_start__..__hpm_init_<stage>_end__`. Lower priorities
+//! run first. Each entry also exports `__hpm_init_<stage>_<priority>`, so two
+//! registrations with the same stage and priority fail to link with a
+//! duplicate symbol error.
+//!
+//! Constructors of linked C/C++ code (`.preinit_array`, then `.init_array` by
+//! `init_priority`) run right before the `pre_main` stage.
+
+/// A registered init function.
+#[doc(hidden)]
+pub type InitFn = unsafe extern "C" fn();
+
+/// Run the `post_ram` stage. Called from `_hpm_start_rust`.
+pub(crate) unsafe fn run_post_ram() {
+    extern "C" {
+        static __hpm_init_post_ram_start__: InitFn;
+        static __hpm_init_post_ram_end__: InitFn;
+    }
+
+    run(
+        core::ptr::addr_of!(__hpm_init_post_ram_start__),
+        core::ptr::addr_of!(__hpm_init_post_ram_end__),
+    );
+}
+
+/// Run the `pre_main` stage. Called from `_hpm_start_rust`.
+pub(crate) unsafe fn run_pre_main() {
+    extern "C" {
+        static __hpm_init_pre_main_start__: InitFn;
+        static __hpm_init_pre_main_end__: InitFn;
+    }
+
+    run(
+        core::ptr::addr_of!(__hpm_init_pre_main_start__),
+        core::ptr::addr_of!(__hpm_init_pre_main_end__),
+    );
+}
+
+/// Run C/C++ constructors, `.preinit_array` then `.init_array`. Called from
+/// `_hpm_start_rust` before the `pre_main` stage.
+pub(crate) unsafe fn run_init_array() {
+    extern "C" {
+        static __preinit_array_start: InitFn;
+        static __preinit_array_end: InitFn;
+        static __init_array_start: InitFn;
+        static __init_array_end: InitFn;
+    }
+
+    run(
+        core::ptr::addr_of!(__preinit_array_start),
+        core::ptr::addr_of!(__preinit_array_end),
+    );
+    run(
+        core::ptr::addr_of!(__init_array_start),
+        core::ptr::addr_of!(__init_array_end),
+    );
+}
+
+unsafe fn run(start: *const InitFn, end: *const InitFn) {
+    let len = end.offset_from(start) as usize;
+    for init in core::slice::from_raw_parts(start, len) {
+        init();
+    }
+}
@@ -0,0 +1,97 @@
+//! Supervisor-mode applications with trap delegation.
+//!
+//! With the `supervisor-mode` feature, `_hpm_start_rust` finishes the setup
+//! in M-mode and enters `main` in S-mode. M-mode stays as a thin monitor:
+//!
+//! - [`DELEGATED_EXCEPTIONS`] and [`DELEGATED_INTERRUPTS`] go to `S_TRAP`
+//!   (`stvec`), which dispatches them through the same tables as
+//!   `CORE_LOCAL`, with the same [`TrapFrame`](crate::TrapFrame).
+//! - Everything else, including access faults, illegal instructions,
+//!   `SupervisorEnvCall`, the machine timer and PLIC interrupts, is still
+//!   taken by `CORE_LOCAL` and the vector table in M-mode.
+//!
+//! Handlers are shared, so `SupervisorTimer` or `SupervisorSoft` run in
+//! S-mode while `MachineTimer` runs in M-mode. An M-mode handler can forward
+//! an event to S-mode by setting the matching pending bit in `mip`, e.g.
+//! `mip::set_stimer()` from `MachineTimer`. Only the M-mode PLIC context is
+//! set up, so PLIC interrupts are not delegated.
+//!
+//! As with `user-mode`, the top [`TRAP_STACK_SIZE`] bytes of `.stack` are
+//! the M-mode trap stack: `main` runs below it, and `CORE_LOCAL` switches to
+//! it (via `mscratch`) for traps from S-mode. After any PMP entries from the
+//! `pmp` feature, an entry denies S-mode access to the trap stack and a last
+//! one gives it access to the rest of the address space, peripherals
+//! included.
+//!
+//! [`TRAP_STACK_SIZE`]: crate::stack::TRAP_STACK_SIZE
+//!
+//! Only for cores with S-mode (D45, e.g. HPM6700).
+
+use riscv::register::{
+    medeleg, mepc, mideleg, mscratch, mstatus, sie,
+    stvec::{self, Stvec, TrapMode},
+};
+
+use crate::pmp::{self, Permission};
+use crate::stack;
+
+/// Exceptions handled in S-mode: misaligned accesses (0, 4, 6), breakpoint
+/// (3), `UserEnvCall` (8) and page faults (12, 13, 15).
+///
+/// With `emulate-misaligned`, misaligned loads and stores (4, 6) stay in
+/// M-mode, where they are emulated.
+pub const DELEGATED_EXCEPTIONS: usize = if cfg!(feature = "emulate-misaligned") {
+    1 << 0 | 1 << 3 | 1 << 8 | 1 << 12 | 1 << 13 | 1 << 15
+} else {
+    1 << 0 | 1 << 3 | 1 << 4 | 1 << 6 | 1 << 8 | 1 << 12 | 1 << 13 | 1 << 15
+};
+
+/// Interrupts handled in S-mode: supervisor software (1) and timer (5).
+pub const DELEGATED_INTERRUPTS: usize = 1 << 1 | 1 << 5;
+
+/// Point `stvec` at `S_TRAP`, delegate traps and open PMP for S-mode.
+///
+/// # Safety
+///
+/// Called once from `_hpm_start_rust`, after PMP and interrupts are set up.
+pub(crate) unsafe fn configure() {
+    extern "C" {
+        fn S_TRAP();
+    }
+
+    stvec::write(Stvec::new(S_TRAP as *const () as usize, TrapMode::Direct));
+    medeleg::write(medeleg::Medeleg::from_bits(DELEGATED_EXCEPTIONS));
+    mideleg::write(mideleg::Mideleg::from_bits(DELEGATED_INTERRUPTS));
+
+    sie::set_ssoft();
+    sie::set_stimer();
+
+    // Deny the trap stack before the catch-all, which has lower priority.
+    // With `pmp`, `pmp::Builder::from_linker` already did.
+    #[cfg(not(feature = "pmp"))]
+    pmp::Builder::new()
+        .region(
+            stack::app_stack_top(),
+            stack::trap_stack_top(),
+            Permission::NONE,
+        )
+        .apply()
+        .expect("no PMP entry left for the trap stack");
+    pmp::allow_remaining(Permission::RWX).expect("no PMP entry left for S-mode");
+}
+
+/// Enter `entry` in S-mode, on the stack below the M-mode trap stack.
+///
+/// `mscratch` is set to the top of the M-mode trap stack, so traps from
+/// S-mode can switch stacks.
+///
+/// # Safety
+///
+/// [`configure`] must have run. Called once from `_hpm_start_rust`.
+pub(crate) unsafe fn enter(entry: unsafe extern "Rust" fn() -> !) -> ! {
+    mscratch::write(stack::trap_stack_top());
+    mstatus::set_mpp(mstatus::MPP::Supervisor);
+    mstatus::set_mpie();
+    mepc::write(entry as usize);
+    core::arch::asm!("mv sp, {0}", "mret", in(reg) stack::app_stack_top(), options(noreturn));
+}
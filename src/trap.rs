@@ -4,13 +4,42 @@
 //! for handling exceptions and core interrupts in PLIC vectored mode.
 //!
 //! In HPMicro's PLIC vectored mode:
-//! - mtvec points to the vector table in ILM
+//! - mtvec points to the vector table in REGION_VECTOR (usually ILM)
 //! - Entry 0 (CORE_LOCAL) handles exceptions and core interrupts
 //! - Entries 1+ are direct jump targets for PLIC external interrupts
+//!
+//! `CORE_LOCAL` and its Rust dispatcher live in `.trap.rust`, which the
+//! linker script places in ILM (checked by a link-time assertion).
+//! With the `fast-trap` feature, the dispatch tables are placed in DLM too.
+//! Handlers should be marked `#[fast]`. The default handlers stay in flash:
+//! they only spin, and lld cannot settle the `PROVIDE` aliases to them once
+//! they move with `.fast`.
+//!
+//! With the `user-mode` feature, `CORE_LOCAL` switches to the M-mode trap
+//! stack for traps from U-mode and `UserEnvCall` is replaced by the syscall
+//! dispatcher, see [`crate::user`]. PLIC vectored mode is not used, so
+//! external interrupts also enter `CORE_LOCAL` and their handlers run on
+//! the trap stack instead of the U-mode stack. The same applies to traps
+//! from S-mode with the `supervisor-mode` feature.
+//!
+//! With the `emulator`, `user-mode` or `supervisor-mode` feature, `mtvec`
+//! points directly at `CORE_LOCAL`, which claims PLIC interrupts, calls
+//! their handlers from the vector table and completes them. See
+//! [`crate::emulator`] for QEMU.
+//!
+//! With the `emulate-misaligned` feature, `CORE_LOCAL` emulates misaligned
+//! loads and stores before dispatch, see [`crate::misaligned`].
+//!
+//! With the `semihosting` feature, `Breakpoint`s on a semihosting call are
+//! stepped over before dispatch, see [`crate::semihosting`].
+//!
+//! With the `supervisor-mode` feature, delegated traps enter `S_TRAP` (also
+//! in ILM) and go through the same dispatch tables, see
+//! [`crate::supervisor`].
 
 use core::arch::global_asm;
 
-use riscv::register::mcause;
+use riscv::register::{mcause, mtval};
 
 use crate::TrapFrame;
 
@@ -37,6 +66,7 @@ extern "C" {
 /// Exception dispatch table.
 #[doc(hidden)]
 #[no_mangle]
+#[cfg_attr(feature = "fast-trap", link_section = ".fast.rodata.trap")]
 pub static __HPM_EXCEPTIONS: [Option<unsafe extern "C" fn(&TrapFrame)>; 16] = [
     Some(InstructionMisaligned), // 0
     Some(InstructionFault),      // 1
@@ -71,6 +101,7 @@ extern "C" {
 /// Core interrupt dispatch table.
 #[doc(hidden)]
 #[no_mangle]
+#[cfg_attr(feature = "fast-trap", link_section = ".fast.rodata.trap")]
 pub static __HPM_CORE_INTERRUPTS: [Option<unsafe extern "C" fn()>; 14] = [
     None,                     // 0 (reserved)
     Some(SupervisorSoft),     // 1
@@ -88,6 +119,21 @@ pub static __HPM_CORE_INTERRUPTS: [Option<unsafe extern "C" fn()>; 14] = [
     None,                     // 13 (Host, reserved)
 ];
 
+// ============ Fault Diagnosis ============
+
+/// Check whether the exception being handled is an access fault inside the
+/// null-pointer guard, i.e. a null pointer dereference or a call through a
+/// null function pointer.
+///
+/// Only meaningful from an `InstructionFault`, `LoadFault` or `StoreFault`
+/// handler, with `_null_guard_size` set in memory.x.
+pub fn is_null_pointer_fault() -> bool {
+    let guard = crate::null_guard_size();
+    let cause = mcause::read();
+    // 1: instruction access fault, 5: load access fault, 7: store access fault
+    cause.is_exception() && matches!(cause.code(), 1 | 5 | 7) && mtval::read() < guard
+}
+
 // ============ CORE_LOCAL Handler ============
 
 /// Rust handler for CORE_LOCAL (vector table entry 0).
@@ -95,7 +141,7 @@ pub static __HPM_CORE_INTERRUPTS: [Option<unsafe extern "C" fn()>; 14] = [
 /// This function dispatches exceptions and core interrupts to their handlers.
 #[no_mangle]
 #[link_section = ".trap.rust"]
-unsafe extern "C" fn _start_rust_CORE_LOCAL(trap_frame: *const TrapFrame) {
+unsafe extern "C" fn _start_rust_CORE_LOCAL(trap_frame: *mut TrapFrame) {
     let cause = mcause::read();
     let code = cause.code();
 
@@ -105,11 +151,70 @@ unsafe extern "C" fn _start_rust_CORE_LOCAL(trap_frame: *const TrapFrame) {
     if cause.is_exception() {
         // HPM6700 Errata: ignore illegal instruction exception with mtval=0
         #[cfg(feature = "hpm67-fix")]
-        if code == 2 && riscv::register::mtval::read() == 0 {
+        if code == 2 && mtval::read() == 0 {
             return;
         }
 
-        let trap_frame = &*trap_frame;
+        // ecall from U-mode: dispatch through the syscall table
+        #[cfg(feature = "user-mode")]
+        if code == 8 {
+            crate::user::dispatch(&mut *trap_frame);
+            return;
+        }
+
+        // Semihosting call without a debugger: step over it
+        #[cfg(feature = "semihosting")]
+        if code == 3 {
+            use riscv::register::mepc;
+            if let Some(pc) = crate::semihosting::skip(&mut *trap_frame, mepc::read()) {
+                mepc::write(pc);
+                return;
+            }
+        }
+    }
+
+    // Direct mode (QEMU, or U/S-mode so handlers run on the trap stack):
+    // claim and dispatch PLIC interrupts here
+    #[cfg(any(
+        feature = "emulator",
+        feature = "user-mode",
+        feature = "supervisor-mode"
+    ))]
+    if !cause.is_exception() && code == 11 {
+        dispatch_external();
+        return;
+    }
+
+    dispatch(cause.is_exception(), code, &*trap_frame);
+}
+
+/// Rust handler for `S_TRAP`, the S-mode trap entry (`stvec`).
+///
+/// Dispatches delegated exceptions and interrupts through the same tables
+/// as `CORE_LOCAL`.
+#[cfg(feature = "supervisor-mode")]
+#[no_mangle]
+#[link_section = ".trap.rust"]
+unsafe extern "C" fn _start_rust_S_TRAP(trap_frame: *mut TrapFrame) {
+    let cause = riscv::register::scause::read();
+
+    // Semihosting call without a debugger: step over it
+    #[cfg(feature = "semihosting")]
+    if cause.is_exception() && cause.code() == 3 {
+        use riscv::register::sepc;
+        if let Some(pc) = crate::semihosting::skip(&mut *trap_frame, sepc::read()) {
+            sepc::write(pc);
+            return;
+        }
+    }
+
+    dispatch(cause.is_exception(), cause.code(), &*trap_frame);
+}
+
+/// Call the handler for an exception or core interrupt `code`.
+#[inline(always)]
+unsafe fn dispatch(is_exception: bool, code: usize, trap_frame: &TrapFrame) {
+    if is_exception {
         if let Some(Some(handler)) = __HPM_EXCEPTIONS.get(code) {
             handler(trap_frame);
         }
@@ -122,18 +227,10 @@ unsafe extern "C" fn _start_rust_CORE_LOCAL(trap_frame: *const TrapFrame) {
     }
 }
 
-// CORE_LOCAL assembly handler.
-// Saves caller-saved registers, calls Rust handler, restores registers.
-global_asm!(
-    r#"
-    .section .trap.rust, "ax"
-    .global CORE_LOCAL
-    .type CORE_LOCAL, @function
-    .balign 4
-
-CORE_LOCAL:
-    /* Save caller-saved registers */
-    addi sp, sp, -(16 * 4)
+// Caller-saved registers in TrapFrame order, shared by both CORE_LOCAL variants.
+macro_rules! save_trap_frame {
+    () => {
+        r#"
     sw ra, 0(sp)
     sw t0, 4(sp)
     sw t1, 8(sp)
@@ -150,14 +247,15 @@ CORE_LOCAL:
     sw a5, 52(sp)
     sw a6, 56(sp)
     sw a7, 60(sp)
+"#
+    };
+}
 
-    /* Call Rust handler with trap frame pointer */
-    mv a0, sp
-    call _start_rust_CORE_LOCAL
-
-    /* Restore caller-saved registers */
+// Everything but t0, which the caller restores last.
+macro_rules! restore_trap_frame {
+    () => {
+        r#"
     lw ra, 0(sp)
-    lw t0, 4(sp)
     lw t1, 8(sp)
     lw t2, 12(sp)
     lw t3, 16(sp)
@@ -172,10 +270,260 @@ CORE_LOCAL:
     lw a5, 52(sp)
     lw a6, 56(sp)
     lw a7, 60(sp)
+"#
+    };
+}
+
+// Call the Rust handler with the trap frame in a0. With `emulate-misaligned`,
+// misaligned loads and stores (mcause 4 and 6) are first given to the
+// emulator, with the sp at the trap in a1 (computed by the argument).
+#[cfg(not(feature = "emulate-misaligned"))]
+macro_rules! call_rust_handler {
+    ($trap_sp:literal) => {
+        r#"
+    mv a0, sp
+    call _start_rust_CORE_LOCAL
+"#
+    };
+}
+
+#[cfg(feature = "emulate-misaligned")]
+macro_rules! call_rust_handler {
+    ($trap_sp:literal) => {
+        concat!(
+            r#"
+    csrr t0, mcause
+    addi t0, t0, -4
+    andi t0, t0, -3     /* 0 for mcause 4 and 6 */
+    bnez t0, 8f
+    mv a0, sp
+"#,
+            $trap_sp,
+            r#"
+    call __hpm_emulate_misaligned
+    bnez a0, 9f
+8:
+    mv a0, sp
+    call _start_rust_CORE_LOCAL
+9:
+"#
+        )
+    };
+}
+
+// CORE_LOCAL assembly handler.
+// Saves caller-saved registers, calls Rust handler, restores registers.
+#[cfg(not(any(feature = "user-mode", feature = "supervisor-mode")))]
+global_asm!(concat!(
+    r#"
+    .section .trap.rust, "ax"
+    .global CORE_LOCAL
+    .type CORE_LOCAL, @function
+    .balign 4
+
+CORE_LOCAL:
+    /* Save caller-saved registers */
+    addi sp, sp, -(16 * 4)
+"#,
+    save_trap_frame!(),
+    r#"
+    /* Call Rust handler with trap frame pointer */
+"#,
+    call_rust_handler!("addi a1, sp, 16 * 4"),
+    r#"
+    /* Restore caller-saved registers */
+"#,
+    restore_trap_frame!(),
+    r#"
+    lw t0, 4(sp)
     addi sp, sp, 16 * 4
 
     mret
 
     .size CORE_LOCAL, . - CORE_LOCAL
 "#
+));
+
+// CORE_LOCAL with a stack switch for traps from U-mode or S-mode.
+// mscratch holds the M-mode trap stack top while U/S-mode runs and 0 in M-mode.
+// The interrupted sp is saved at 64(sp), after the TrapFrame (0 if from M-mode).
+#[cfg(any(feature = "user-mode", feature = "supervisor-mode"))]
+global_asm!(concat!(
+    r#"
+    .section .trap.rust, "ax"
+    .global CORE_LOCAL
+    .type CORE_LOCAL, @function
+    .balign 4
+
+CORE_LOCAL:
+    /* From U/S-mode: swap to the trap stack. From M-mode: swap back. */
+    csrrw sp, mscratch, sp
+    bnez sp, 1f
+    csrrw sp, mscratch, sp
+1:
+    /* Save caller-saved registers and the interrupted sp */
+    addi sp, sp, -(20 * 4)
+"#,
+    save_trap_frame!(),
+    r#"
+    csrrw t0, mscratch, zero
+    sw t0, 64(sp)
+
+    /* Call Rust handler with trap frame pointer */
+"#,
+    call_rust_handler!(
+        r#"
+    lw a1, 64(sp)
+    bnez a1, 7f
+    addi a1, sp, 20 * 4
+7:
+"#
+    ),
+    r#"
+    /* Restore caller-saved registers */
+"#,
+    restore_trap_frame!(),
+    r#"
+    lw t0, 64(sp)
+    beqz t0, 1f
+
+    /* Back to U/S-mode: trap stack top into mscratch, its sp into sp */
+    csrw mscratch, t0
+    lw t0, 4(sp)
+    addi sp, sp, 20 * 4
+    csrrw sp, mscratch, sp
+    mret
+1:
+    lw t0, 4(sp)
+    addi sp, sp, 20 * 4
+    mret
+
+    .size CORE_LOCAL, . - CORE_LOCAL
+"#
+));
+
+// S-mode trap entry for exceptions and interrupts delegated by the M-mode
+// monitor. Same frame layout as CORE_LOCAL, returns with sret.
+#[cfg(feature = "supervisor-mode")]
+global_asm!(concat!(
+    r#"
+    .section .trap.rust, "ax"
+    .global S_TRAP
+    .type S_TRAP, @function
+    .balign 4
+
+S_TRAP:
+    /* Save caller-saved registers */
+    addi sp, sp, -(16 * 4)
+"#,
+    save_trap_frame!(),
+    r#"
+    /* Call Rust handler with trap frame pointer */
+    mv a0, sp
+    call _start_rust_S_TRAP
+
+    /* Restore caller-saved registers */
+"#,
+    restore_trap_frame!(),
+    r#"
+    lw t0, 4(sp)
+    addi sp, sp, 16 * 4
+
+    sret
+
+    .size S_TRAP, . - S_TRAP
+"#
+));
+
+// ============ Direct-Mode External Interrupts ============
+
+// Call a `riscv-interrupt-m` handler from a trap: it returns with `mret`, so
+// point `mepc` at the continuation and make `mret` stay in M-mode with
+// interrupts disabled. The interrupted `mepc` and `mstatus` are restored
+// afterwards. The handler preserves all registers.
+#[cfg(any(
+    feature = "emulator",
+    feature = "user-mode",
+    feature = "supervisor-mode"
+))]
+global_asm!(
+    r#"
+    .section .trap.rust, "ax"
+    .global __hpm_call_interrupt_handler
+    .type __hpm_call_interrupt_handler, @function
+    .balign 4
+
+__hpm_call_interrupt_handler:
+    addi sp, sp, -16
+    sw ra, 0(sp)
+    csrr t0, mepc
+    sw t0, 4(sp)
+    csrr t0, mstatus
+    sw t0, 8(sp)
+
+    la t0, 1f
+    csrw mepc, t0
+    /* MPP = M, MPIE = 0 */
+    li t0, 0x1800
+    csrs mstatus, t0
+    li t0, 0x80
+    csrc mstatus, t0
+    jr a0
+
+1:
+    lw t0, 8(sp)
+    csrw mstatus, t0
+    lw t0, 4(sp)
+    csrw mepc, t0
+    lw ra, 0(sp)
+    addi sp, sp, 16
+    ret
+
+    .size __hpm_call_interrupt_handler, . - __hpm_call_interrupt_handler
+"#
 );
+
+/// Handle `MachineExternal` in direct mode: claim, call the handler from
+/// the vector table and complete, until no interrupt is pending.
+#[cfg(any(
+    feature = "emulator",
+    feature = "user-mode",
+    feature = "supervisor-mode"
+))]
+#[inline(always)]
+unsafe fn dispatch_external() {
+    use andes_riscv::plic::{Plic, PlicExt};
+
+    extern "C" {
+        static __vector_ram_start__: usize;
+        static __vector_ram_end__: usize;
+        fn __hpm_call_interrupt_handler(handler: usize);
+        fn DefaultHandler();
+    }
+
+    let plic = Plic::from_ptr(crate::PLIC_BASE as *mut ());
+    let table = core::ptr::addr_of!(__vector_ram_start__);
+    let len = core::ptr::addr_of!(__vector_ram_end__).offset_from(table) as usize;
+    loop {
+        let id = plic.claim();
+        if id == 0 {
+            break;
+        }
+        // Entry 0 is CORE_LOCAL, entry N the handler of interrupt N
+        let handler = if (id as usize) < len {
+            table.add(id as usize).read()
+        } else {
+            0
+        };
+        if handler != 0 {
+            __hpm_call_interrupt_handler(handler);
+        } else {
+            DefaultHandler();
+        }
+        // Not `PlicExt::complete`, whose read-modify-write claims the next
+        // pending interrupt
+        plic.targetconfig(0)
+            .claim()
+            .write(|w| w.set_interrupt_id(id));
+    }
+}
@@ -0,0 +1,122 @@
+//! User-mode applications and `ecall` syscall dispatch.
+//!
+//! With the `user-mode` feature, `_hpm_start_rust` enters `main` in U-mode
+//! instead of calling it. The PMP entries from [`pmp::Builder::from_linker`]
+//! give the application access to its image (text, rodata, data, heap and
+//! stack) and nothing else: peripherals are only reachable from M-mode, through
+//! syscalls provided by trusted code.
+//!
+//! The top [`TRAP_STACK_SIZE`] bytes of `.stack` are kept for M-mode and are
+//! not accessible from U-mode. `CORE_LOCAL` switches to that stack (via
+//! `mscratch`) when a trap is taken from U-mode.
+//!
+//! A syscall is a function registered with `#[syscall(N)]`. An `ecall` from
+//! U-mode with `a7 = N` calls it with up to six arguments from `a0..a5` and
+//! writes the return value to `a0`. Unknown numbers return
+//! [`UNKNOWN_SYSCALL`]. Handlers run in M-mode with interrupts disabled.
+//!
+//! ```ignore
+//! use hpm_riscv_rt::{syscall, user};
+//!
+//! #[syscall(1)]
+//! fn led_set(on: usize) {
+//!     // Access GPIO from M-mode
+//! }
+//!
+//! #[entry]
+//! fn main() -> ! {
+//!     // Runs in U-mode
+//!     unsafe { user::syscall(1, [1, 0, 0, 0, 0, 0]) };
+//!     loop {}
+//! }
+//! ```
+//!
+//! PLIC vectored mode is not used, so PLIC interrupts also enter
+//! `CORE_LOCAL`, which claims them and calls their handlers
+//! (`#[external_interrupt]`) from the vector table on the trap stack. The
+//! application's stack is never used by M-mode code.
+//!
+//! [`pmp::Builder::from_linker`]: crate::pmp::Builder::from_linker
+
+use riscv::register::{mepc, mscratch, mstatus};
+
+use crate::TrapFrame;
+
+pub use crate::stack::TRAP_STACK_SIZE;
+
+/// Return value of an `ecall` with no registered handler.
+pub const UNKNOWN_SYSCALL: usize = usize::MAX;
+
+/// Syscall table entry, generated by `#[syscall(N)]`.
+#[doc(hidden)]
+#[repr(C)]
+pub struct SyscallEntry {
+    /// Syscall number (`a7`)
+    pub id: usize,
+    /// Trampoline that reads the arguments from and writes the result to the
+    /// trap frame
+    pub handler: unsafe extern "C" fn(&mut TrapFrame),
+}
+
+// SAFETY: entries are immutable after link time.
+unsafe impl Sync for SyscallEntry {}
+
+/// Issue syscall `id` with arguments in `a0..a5` and return `a0`.
+///
+/// # Safety
+///
+/// The handler for `id` may have arbitrary effects; see its documentation.
+#[inline(always)]
+pub unsafe fn syscall(id: usize, args: [usize; 6]) -> usize {
+    let ret;
+    core::arch::asm!(
+        "ecall",
+        inlateout("a0") args[0] => ret,
+        in("a1") args[1],
+        in("a2") args[2],
+        in("a3") args[3],
+        in("a4") args[4],
+        in("a5") args[5],
+        in("a7") id,
+        options(nostack)
+    );
+    ret
+}
+
+/// Enter `entry` in U-mode on `stack`.
+///
+/// `mscratch` is set to the top of the M-mode trap stack, so traps from
+/// U-mode can switch stacks.
+///
+/// # Safety
+///
+/// PMP must give U-mode access to the code and data `entry` uses, and
+/// `stack` must be accessible from U-mode. Called once from `_hpm_start_rust`.
+pub(crate) unsafe fn enter(entry: unsafe extern "Rust" fn() -> !, stack: usize) -> ! {
+    mscratch::write(crate::stack::trap_stack_top());
+    mstatus::set_mpp(mstatus::MPP::User);
+    mstatus::set_mpie();
+    mepc::write(entry as usize);
+    core::arch::asm!("mv sp, {0}", "mret", in(reg) stack, options(noreturn));
+}
+
+/// Dispatch an `ecall` from U-mode through the syscall table and step over
+/// the `ecall` instruction.
+pub(crate) unsafe fn dispatch(frame: &mut TrapFrame) {
+    extern "C" {
+        static __syscall_table_start__: SyscallEntry;
+        static __syscall_table_end__: SyscallEntry;
+    }
+
+    let start = core::ptr::addr_of!(__syscall_table_start__);
+    let end = core::ptr::addr_of!(__syscall_table_end__);
+    let len = end.offset_from(start) as usize;
+    let table = core::slice::from_raw_parts(start, len);
+
+    match table.iter().find(|e| e.id == frame.a7) {
+        Some(entry) => (entry.handler)(frame),
+        None => frame.a0 = UNKNOWN_SYSCALL,
+    }
+
+    mepc::write(mepc::read() + 4);
+}